@@ -1,14 +1,111 @@
-use std::{env, path::PathBuf, process::Command};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-pub fn main() {
-    let mut css_out = PathBuf::from(env::var("OUT_DIR").unwrap());
-    css_out.push("out.css");
-    let _ = Command::new("npx")
+/// Files to embed, by the name they are served as
+fn collect_assets(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        if path.is_dir() {
+            collect_assets(&path, &format!("{name}/"), out);
+        } else {
+            out.push((name, path));
+        }
+    }
+}
+
+/// FNV-1a, only has to change when the file does
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit('.').next() {
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Runs tailwind over main.css and the classes used in src, stops the build if that fails
+fn tailwind(css_out: &Path) {
+    let output = Command::new("npx")
+        .arg("--no-install")
         .arg("tailwindcss")
         .arg("-i")
         .arg("./main.css")
         .arg("-o")
         .arg(css_out)
         .arg("--minify")
-        .spawn();
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => panic!(
+            "tailwindcss failed ({}):\n{}\nbuild without the tailwind feature to use main.min.css instead",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Err(err) => panic!(
+            "couldn't run npx: {err}\nbuild without the tailwind feature to use main.min.css instead"
+        ),
+    }
+}
+
+pub fn main() {
+    println!("cargo:rerun-if-changed=main.css");
+    println!("cargo:rerun-if-changed=main.min.css");
+    println!("cargo:rerun-if-changed=assets");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let css = if env::var_os("CARGO_FEATURE_TAILWIND").is_some() {
+        println!("cargo:rerun-if-changed=tailwind.config.js");
+        println!("cargo:rerun-if-changed=src");
+        let css_out = out_dir.join("out.css");
+        tailwind(&css_out);
+        css_out
+    } else {
+        PathBuf::from("main.min.css")
+    };
+
+    let mut assets = vec![("main.css".to_owned(), css)];
+    collect_assets(Path::new("assets"), "", &mut assets);
+    assets[1..].sort();
+    // pages load htmx from unpkg without these, so it's only worth a warning
+    for file in ["htmx.min.js", "ext/sse.js"] {
+        if !assets.iter().any(|(name, _)| name == file) {
+            println!("cargo:warning=assets/{file} is missing, pages will load it from unpkg");
+        }
+    }
+
+    let mut table = String::from("pub static ASSETS: &[Asset] = &[\n");
+    for (name, path) in assets {
+        let path = fs::canonicalize(&path).unwrap();
+        let hash = format!("{:016x}", hash(&fs::read(&path).unwrap()));
+        let hashed_name = match name.rsplit_once('.') {
+            Some((stem, ext)) if !ext.contains('/') => format!("{stem}.{hash}.{ext}"),
+            _ => format!("{name}.{hash}"),
+        };
+        table += &format!(
+            "    Asset {{ name: {name:?}, hashed_name: {hashed_name:?}, hash: {hash:?}, \
+             content_type: {:?}, body: include_bytes!({path:?}) }},\n",
+            content_type(&name)
+        );
+    }
+    table += "];\n";
+    // rewriting it unchanged would rebuild the whole crate
+    let table_out = out_dir.join("assets.rs");
+    if fs::read_to_string(&table_out).ok() != Some(table.clone()) {
+        fs::write(table_out, table).unwrap();
+    }
 }
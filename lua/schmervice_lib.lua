@@ -82,14 +82,20 @@ end
 
 ---@param to_user string the username of the buyer
 ---@param transaction_name string the Title of the Transaction that gets displayed to the user
----@param transaction_amount integer the amount of money of this transaction
+---@param transaction_amount string | number the amount of money of this transaction, like "2.50"; gets send as a string so no precision is lost
 ---@param user user the user that acts as the seller in this transaction
 ---@param ... any these will be passed into the event emitted as args
 ---@return function awaitable please run this function using the paralel or just blocking, ig
 function M:make_transaction(to_user, transaction_name, transaction_amount, user, ...)
-    local request_data = { buyer = to_user, amount = transaction_amount, name = transaction_name }
+    local request_data = { buyer = to_user, amount = tostring(transaction_amount), name = transaction_name }
     local resp = self:make_authed_api_request("/api/request_transaction", user, request_data)
+    return self:await_transaction(resp, ...)
+end
 
+---@param resp table the response of /api/request_transaction
+---@param ... any these will be passed into the event emitted as args
+---@return function awaitable
+function M:await_transaction(resp, ...)
     -- local headers = { ["Money-Auth-Key"] = user:token() }
     local socket, err = http.websocket("/api/notify_transaction/" .. resp[1])
 
@@ -103,14 +109,144 @@ function M:make_transaction(to_user, transaction_name, transaction_amount, user,
     return function()
         while true do
             local msg = socket.receive()
-            if msg == "transaction_rejected" then break end
             if msg == "transaction_accepted" then
                 os.queueEvent("money:on_transaction_complete", args)
             end
+            -- rejected, cancelled, expired or the socket got closed
+            if msg == nil or msg:sub(1, 12) == "transaction_" then break end
         end
     end
 end
 
+---@alias receipt {id: string, shop: string, code: string, total: string, currency: string, pages: string[][]}
+
+---@param user user the seller or the buyer
+---@param transaction_id string an accepted transaction
+---@param shop? string the id of the shop to print on top, the sellers first shop if nil
+---@return receipt | nil
+function M:get_receipt(user, transaction_id, shop)
+    local receipt, err = self:make_authed_api_request("/api/receipt/" .. transaction_id, user, { shop = shop })
+    if err ~= nil then
+        self.err("Unable to get receipt", err)
+        return nil
+    end
+    return receipt
+end
+
+---Prints the receipt of a transaction, one printer page per page of the receipt
+---@param printer table a wrapped printer peripheral
+---@param user user the seller or the buyer
+---@param transaction_id string an accepted transaction
+---@param shop? string the id of the shop to print on top
+---@return boolean printed false if there was no receipt, ink or paper
+function M:print_receipt(printer, user, transaction_id, shop)
+    local receipt = self:get_receipt(user, transaction_id, shop)
+    if receipt == nil then return false end
+    for i, page in ipairs(receipt.pages) do
+        if not printer.newPage() then
+            self.err("Printer is out of paper or ink")
+            return false
+        end
+        printer.setPageTitle("Receipt " .. receipt.id .. " " .. i .. "/" .. #receipt.pages)
+        for y, line in ipairs(page) do
+            printer.setCursorPos(1, y)
+            printer.write(line)
+        end
+        printer.endPage()
+    end
+    return true
+end
+
+---@param id string the transaction id printed on the receipt
+---@param code string the code printed on the receipt
+---@return boolean valid
+---@return table details seller, buyer, amount, currency, status and timestamp if valid
+function M:verify_receipt(id, code)
+    local resp, err = self:make_api_request("/api/verify_receipt", { id = id, code = code })
+    if err ~= nil then
+        self.err("Unable to verify receipt", err)
+        return false, {}
+    end
+    return resp.valid == true, resp
+end
+
+---Asks the buyer to put money on hold, for when something can still go wrong after paying,
+---like a vending machine that might fail to dispense. Once the hold is approved
+---capture_hold takes the money (or part of it), release_hold gives it back.
+---@param to_user string the username of the buyer
+---@param hold_name string gets displayed to the buyer
+---@param amount string | number the most that can be captured, like "2.50"
+---@param user user the seller
+---@return string | nil hold_id
+---@return function awaitable returns true once the buyer approved, false if they declined
+function M:request_hold(to_user, hold_name, amount, user)
+    local resp, err = self:make_authed_api_request("/api/holds/request_hold", user,
+        { buyer = to_user, name = hold_name, amount = tostring(amount) })
+    if err ~= nil then
+        self.err("Unable to request hold", err)
+        return nil, function() return false end
+    end
+    local hold_id = resp[1]
+    local socket, socket_err = http.websocket(self.server_url .. "/api/holds/notify_hold/" .. hold_id,
+        { ["Money-Auth-Key"] = user:token(), ["Accept"] = "custom/ws" })
+    if socket == false then
+        self.err(socket_err)
+        return hold_id, function() return false end
+    end
+    return hold_id, function()
+        local msg = socket.receive()
+        return msg == "hold_held"
+    end
+end
+
+---@param user user the seller
+---@param hold_id string
+---@param amount? string | number how much to take, everything if nil, the rest goes back to the buyer
+function M:capture_hold(user, hold_id, amount)
+    local data = {}
+    if amount ~= nil then
+        data.amount = tostring(amount)
+    end
+    local _, err = self:make_authed_api_request("/api/holds/capture_hold/" .. hold_id, user, data)
+    if err ~= nil then
+        self.err("Unable to capture hold", err)
+    end
+end
+
+---@param user user the seller
+---@param hold_id string
+function M:release_hold(user, hold_id)
+    local _, err = self:make_authed_api_request("/api/holds/release_hold/" .. hold_id, user, {})
+    if err ~= nil then
+        self.err("Unable to release hold", err)
+    end
+end
+
+---@alias product {sku: string, title: string, description: string, price: string, currency: string, stock: integer}
+
+---@param seller string the username of the seller
+---@return product[] products everything the seller has in their catalog
+function M:get_products(seller)
+    local products, err = self:make_api_request("/api/catalog/list_products", { seller = seller })
+    if err ~= nil then
+        self.err("Unable to get products", err)
+        return {}
+    end
+    return products
+end
+
+---Like make_transaction, but the name, price and currency come from the sellers catalog
+---and one of the product is taken out of stock when the buyer accepts
+---@param to_user string the username of the buyer
+---@param sku string the sku of the product in the sellers catalog
+---@param user user the user that acts as the seller in this transaction
+---@param ... any these will be passed into the event emitted as args
+---@return function awaitable please run this function using the paralel or just blocking, ig
+function M:sell_product(to_user, sku, user, ...)
+    return self:await_transaction(self:make_authed_api_request("/api/request_transaction", user,
+        { buyer = to_user, sku = sku }), ...)
+end
+
 ---@param endpoint string the endpoint to hit include the begining  /
 ---@param data table the data to send to the endpoint
 ---@param headers? table<HTTP_REQUEST_HEADERS | AUTH-HEADER>
@@ -224,10 +360,22 @@ end
 M.SCHMERVICE_LIST_FREQ = 53517
 M.SCHMERVICE_LIST_RESP_FREQ = 45437
 
----@alias schmervice_ref {name:string,rednet_id:number,in_use: boolean}
+---@alias schmervice_ref {name:string,rednet_id:number,in_use: boolean,owner?: string}
 
 ---@param schmervice schmervice_ref
-function M:send_join_request_to_schmervice(username, schmervice)
+---@param user? user if given and the schmervice is on the server, it's claimed or released there first
+function M:send_join_request_to_schmervice(username, schmervice, user)
+    if user ~= nil and schmervice.owner ~= nil then
+        local endpoint = "/api/schmervices/claim"
+        if schmervice.in_use then
+            endpoint = "/api/schmervices/release"
+        end
+        local _, err = self:make_authed_api_request(endpoint, user,
+            { owner = schmervice.owner, name = schmervice.name })
+        if err ~= nil then
+            return "REJECTED"
+        end
+    end
     rednet.send(schmervice.rednet_id, { schmervice_name = schmervice.name, username = username },
         "SCHMERVICE_JOIN_REQUEST")
 
@@ -244,7 +392,9 @@ function M:send_join_request_to_schmervice(username, schmervice)
     return msg
 end
 
-function M:handle_schmervice_join_requests()
+---@param user? user the owner of the schmervices, if given the server decides who uses them:
+---players claim and release them there themselves, a join request only makes this check again
+function M:handle_schmervice_join_requests(user)
     local m = self
     return function()
         while true do
@@ -254,12 +404,17 @@ function M:handle_schmervice_join_requests()
                 local schm = m.schmervices:find(data.schmervice_name)
                 if schm ~= nil and schm.enabled then
                     print(data.username .. ":" .. data.schmervice_name)
-                    -- Maybe put this into own request?
-                    if schm.in_use and schm.username == data.username then
+                    if user ~= nil then
+                        m:sync_schmervices(user)
+                    elseif schm.in_use and schm.username == data.username then
+                        -- Maybe put this into own request?
                         schm.in_use = false
                         schm.username = ""
                     else
                         schm.in_use = not schm.in_use
+                        if schm.in_use then
+                            schm.username = data.username
+                        end
                     end
                 end
             end
@@ -267,6 +422,78 @@ function M:handle_schmervice_join_requests()
     end
 end
 
+---@param user user the owner of the schmervice
+---@param schmervice schmervice
+function M:register_schmervice(user, schmervice)
+    local _, err = self:make_authed_api_request("/api/schmervices/register_schmervice", user,
+        { name = schmervice.name, enabled = schmervice.enabled })
+    if err ~= nil then
+        self.err("Unable to register schmervice", err)
+    end
+end
+
+---Starts using someone elses schmervice as the player,
+---paid schmervices put a hold for max_minutes of use on the players money
+---@param user user the player
+---@param owner string the username of the owner
+---@param name string
+---@param max_minutes? integer 60 by default
+---@return boolean claimed
+function M:claim_schmervice(user, owner, name, max_minutes)
+    local _, err = self:make_authed_api_request("/api/schmervices/claim", user,
+        { owner = owner, name = name, max_minutes = max_minutes })
+    if err ~= nil then
+        self.err("Unable to claim schmervice", err)
+        return false
+    end
+    return true
+end
+
+---Stops using the schmervice, paid ones get billed for every started minute
+---@param user user the player
+---@param owner string the username of the owner
+---@param name string
+function M:release_schmervice(user, owner, name)
+    local _, err = self:make_authed_api_request("/api/schmervices/release", user,
+        { owner = owner, name = name })
+    if err ~= nil then
+        self.err("Unable to release schmervice", err)
+    end
+end
+
+---Sends a heartbeat and takes over the schmervices the server knows with who uses them, call it after a reboot
+---@param user user the owner of the schmervices
+function M:sync_schmervices(user)
+    local remote, err = self:make_authed_api_request("/api/schmervices/heartbeat", user, {})
+    if err ~= nil then
+        self.err("Unable to sync schmervices", err)
+        return
+    end
+    for _, r in ipairs(remote) do
+        local schm = self.schmervices:find(r.name)
+        if schm == nil then
+            schm = self.new_schmervice(r.name, r.enabled)
+            self.schmervices:insert(schm)
+        end
+        schm.in_use = r.in_use
+        schm.username = r.username
+        schm.enabled = r.enabled
+    end
+end
+
+---@param user user the owner of the schmervices
+---@param interval? number seconds between heartbeats, 30 by default
+---@return fun() loop run it in parallel with the request handlers
+function M:schmervice_heartbeat(user, interval)
+    local m = self
+    return function()
+        while true do
+            m:sync_schmervices(user)
+            os.sleep(interval or 30)
+        end
+    end
+end
+
 ---@param modem Modem
 ---@param max_distance number
 ---@return schmervice_ref[]
@@ -282,7 +509,7 @@ function M:get_schmervices_nearby(modem, max_distance)
                 local name = data.name
                 ---@type boolean
                 local in_use = data.in_use
-                table.insert(messages, { name = name, rednet_id = return_channel, in_use = in_use })
+                table.insert(messages, { name = name, rednet_id = return_channel, in_use = in_use, owner = data.owner })
             end
         end
     end
@@ -292,14 +519,15 @@ function M:get_schmervices_nearby(modem, max_distance)
 end
 
 ---@param modem Modem
-function M:handle_schmervice_list_requests(modem)
+---@param owner? string username of the owner, lets players claim the schmervices on the server
+function M:handle_schmervice_list_requests(modem, owner)
     modem.open(self.SCHMERVICE_LIST_FREQ)
     return function()
         while true do
             local _, _, recived_channel, return_channel, _ = os.pullEvent("modem_message")
             if recived_channel == self.SCHMERVICE_LIST_FREQ then
                 for _, v in ipairs(self.schmervices) do
-                    modem.transmit(return_channel, os.computerID(), { name = v.name, in_use = v.in_use })
+                    modem.transmit(return_channel, os.computerID(), { name = v.name, in_use = v.in_use, owner = owner })
                 end
             end
         end
@@ -10,6 +10,13 @@ local schmervice_lib = assert(loadstring(util_code))()
 
 -- Start
 
+-- the seller this shop belongs to
+local username = ...
+if username == nil then
+    write("Username: ")
+    username = io.read()
+end
+
 ---@type Modem
 ---@diagnostic disable-next-line: assign-type-mismatch
 local modem = peripheral.wrap "left" or error("No Modem at left", 0)
@@ -30,7 +37,7 @@ local f = fs.open("token", "r")
 if f == nil then
     local otp = tonumber(io.read())
     ---@diagnostic disable-next-line: param-type-mismatch
-    token = schmervice_lib:login("schmarni", otp)
+    token = schmervice_lib:login(username, otp)
     print(token)
     ---@type WriteHandle
     ---@diagnostic disable-next-line: assign-type-mismatch
@@ -46,11 +53,13 @@ end
 rednet.open("left")
 local user = schmervice_lib:get_user(token)
 print(user:username())
-schmervice_lib.schmervices:insert(schmervice_lib.new_schmervice("Hello World!"))
-schmervice_lib.schmervices:insert(schmervice_lib.new_schmervice("wow how cool"))
-schmervice_lib.schmervices:insert(schmervice_lib.new_schmervice("nice chicken"))
-local w = schmervice_lib.new_schmervice("i am bored")
-w.in_use = true
-schmervice_lib.schmervices:insert(w)
-
-parallel.waitForAny(schmervice_lib:handle_schmervice_list_requests(modem), schmervice_lib:handle_schmervice_join_requests())
+-- the offerings live in the catalog on the server, add them with /api/catalog/add_product
+for _, product in ipairs(schmervice_lib:get_products(username)) do
+    print(product.sku .. " " .. product.title .. ": " .. product.price .. " " .. product.currency
+        .. " (" .. product.stock .. " left)")
+end
+-- the schmervices registered on the server, with whoever used them before a reboot
+schmervice_lib:sync_schmervices(user)
+
+parallel.waitForAny(schmervice_lib:handle_schmervice_list_requests(modem, username),
+    schmervice_lib:handle_schmervice_join_requests(user), schmervice_lib:schmervice_heartbeat(user))
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- names that were taken before this was checked get their username added,
+-- cut down so they still fit the 32 characters register allows
+UPDATE users SET display_name =
+        substr(display_name, 1, max(29 - length(username), 1)) || ' (' || substr(username, 1, 27) || ')'
+WHERE rowid NOT IN (SELECT MIN(rowid) FROM users GROUP BY display_name COLLATE NOCASE);
+CREATE UNIQUE INDEX users_display_name ON users (display_name COLLATE NOCASE);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- a player can't keep the treasury's name, display names are unique
+UPDATE users SET display_name =
+        substr(display_name, 1, max(29 - length(username), 1)) || ' (' || substr(username, 1, 27) || ')'
+WHERE display_name = 'Treasury' COLLATE NOCASE AND username != 'treasury';
+-- system account that receives the money of deleted accounts, it can not be logged into
+INSERT OR IGNORE INTO users (username, display_name, secret, money, otp_verified, role, frozen)
+VALUES ('treasury', 'Treasury', '', 0, FALSE, 'system', FALSE);
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- every change to the total amount of money, SUM(amount) always equals SUM(users.money)
+CREATE TABLE IF NOT EXISTS supply_changes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        admin TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        reason TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+);
+
+-- the starting balances that were handed out before the treasury existed
+INSERT INTO supply_changes (admin, amount, reason, timestamp)
+SELECT 'system', COALESCE(SUM(money), 0), 'Money in circulation before the treasury', strftime('%s', 'now')
+FROM users;
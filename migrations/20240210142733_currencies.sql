@@ -0,0 +1,27 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS currencies (
+        code TEXT NOT NULL PRIMARY KEY,
+        name TEXT NOT NULL
+);
+INSERT OR IGNORE INTO currencies VALUES ('COIN', 'Coins');
+
+CREATE TABLE IF NOT EXISTS balances (
+        username TEXT NOT NULL,
+        currency TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        PRIMARY KEY (username, currency)
+);
+INSERT INTO balances SELECT username, 'COIN', money FROM users;
+ALTER TABLE users DROP COLUMN money;
+
+ALTER TABLE transactions ADD COLUMN currency TEXT NOT NULL DEFAULT 'COIN';
+ALTER TABLE supply_changes ADD COLUMN currency TEXT NOT NULL DEFAULT 'COIN';
+
+-- 1 `from_currency` is worth `rate` / 10^`rate_decimals` `to_currency`
+CREATE TABLE IF NOT EXISTS exchange_rates (
+        from_currency TEXT NOT NULL,
+        to_currency TEXT NOT NULL,
+        rate INTEGER NOT NULL,
+        rate_decimals INTEGER NOT NULL,
+        PRIMARY KEY (from_currency, to_currency)
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- all amounts in the db are stored in the smallest unit of their currency,
+-- `decimals` says how many of those make up one whole unit (10^decimals)
+ALTER TABLE currencies ADD COLUMN decimals INTEGER NOT NULL DEFAULT 0;
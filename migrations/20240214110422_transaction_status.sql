@@ -0,0 +1,3 @@
+-- Add migration script here
+-- 0 = pending, 1 = accepted, 2 = rejected, 3 = cancelled, 4 = expired, 5 = refunded
+ALTER TABLE transactions RENAME COLUMN accepted TO status;
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS products (
+        seller TEXT NOT NULL,
+        sku TEXT NOT NULL,
+        title TEXT NOT NULL,
+        description TEXT NOT NULL DEFAULT '',
+        price INTEGER NOT NULL,
+        currency TEXT NOT NULL DEFAULT 'COIN',
+        stock INTEGER NOT NULL,
+        PRIMARY KEY (seller, sku)
+);
+
+-- the product a transaction is for, NULL for free-form transactions
+ALTER TABLE transactions ADD COLUMN sku TEXT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS shops (
+        id TEXT NOT NULL PRIMARY KEY,
+        owner TEXT NOT NULL,
+        name TEXT NOT NULL,
+        description TEXT NOT NULL DEFAULT '',
+        x INTEGER NOT NULL,
+        y INTEGER NOT NULL,
+        z INTEGER NOT NULL,
+        dimension TEXT NOT NULL DEFAULT 'minecraft:overworld',
+        open BOOLEAN NOT NULL DEFAULT 1,
+        created INTEGER NOT NULL
+);
@@ -0,0 +1,20 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS schmervices (
+        owner TEXT NOT NULL,
+        name TEXT NOT NULL,
+        enabled BOOLEAN NOT NULL DEFAULT 1,
+        -- the user currently using the schmervice, NULL when it's free
+        in_use_by TEXT,
+        claimed_at INTEGER,
+        last_heartbeat INTEGER NOT NULL,
+        PRIMARY KEY (owner, name)
+);
+
+CREATE TABLE IF NOT EXISTS schmervice_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner TEXT NOT NULL,
+        name TEXT NOT NULL,
+        username TEXT NOT NULL,
+        action TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+);
@@ -0,0 +1,31 @@
+-- Add migration script here
+-- money taken from the buyer that the seller can capture later
+CREATE TABLE IF NOT EXISTS holds (
+        id TEXT NOT NULL PRIMARY KEY,
+        buyer TEXT NOT NULL,
+        seller TEXT NOT NULL,
+        name TEXT NOT NULL,
+        currency TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        captured INTEGER NOT NULL DEFAULT 0,
+        status INTEGER NOT NULL DEFAULT 0,
+        created INTEGER NOT NULL
+);
+
+-- price per started minute of use, 0 means free
+ALTER TABLE schmervices ADD COLUMN rate INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE schmervices ADD COLUMN currency TEXT NOT NULL DEFAULT 'COIN';
+
+CREATE TABLE IF NOT EXISTS sessions (
+        id TEXT NOT NULL PRIMARY KEY,
+        owner TEXT NOT NULL,
+        name TEXT NOT NULL,
+        username TEXT NOT NULL,
+        hold_id TEXT NOT NULL,
+        rate INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        max_minutes INTEGER NOT NULL,
+        started INTEGER NOT NULL,
+        ended INTEGER,
+        cost INTEGER
+);
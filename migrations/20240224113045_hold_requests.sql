@@ -0,0 +1,4 @@
+-- Add migration script here
+-- when the buyer approved the hold and the money got taken, NULL while it's only requested
+ALTER TABLE holds ADD COLUMN approved INTEGER;
+UPDATE holds SET approved = created;
@@ -0,0 +1,24 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS subscriptions (
+        id TEXT NOT NULL PRIMARY KEY,
+        buyer TEXT NOT NULL,
+        seller TEXT NOT NULL,
+        name TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        interval_hours INTEGER NOT NULL,
+        status INTEGER NOT NULL DEFAULT 0,
+        next_charge INTEGER,
+        -- when the first charge that couldn't be paid was due, NULL while everything is paid
+        due_since INTEGER,
+        failed_attempts INTEGER NOT NULL DEFAULT 0,
+        created INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS notifications (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        username TEXT NOT NULL,
+        message TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        read BOOLEAN NOT NULL DEFAULT 0
+);
@@ -0,0 +1,16 @@
+-- Add migration script here
+-- the lines of a transaction that was requested as an invoice, transactions without any
+-- are a single line of their name and amount
+CREATE TABLE IF NOT EXISTS invoice_lines (
+        transaction_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        kind INTEGER NOT NULL,
+        description TEXT NOT NULL,
+        -- items only
+        quantity INTEGER,
+        unit_price INTEGER,
+        -- taxes only, 1/100 of a percent
+        basis_points INTEGER,
+        amount INTEGER NOT NULL,
+        PRIMARY KEY (transaction_id, position)
+);
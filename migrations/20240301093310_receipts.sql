@@ -0,0 +1,3 @@
+-- Add migration script here
+-- handed out with the first receipt of an accepted transaction, proves the receipt is real
+ALTER TABLE transactions ADD COLUMN receipt_code TEXT;
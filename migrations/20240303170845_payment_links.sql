@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS payment_links (
+        code TEXT NOT NULL PRIMARY KEY,
+        seller TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        currency TEXT NOT NULL DEFAULT 'COIN',
+        memo TEXT NOT NULL,
+        status INTEGER NOT NULL DEFAULT 0,
+        created INTEGER NOT NULL,
+        paid_by TEXT,
+        transaction_id TEXT
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE auth_tokens ADD COLUMN client INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE auth_tokens ADD COLUMN created INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX auth_tokens_username ON auth_tokens (username, client);
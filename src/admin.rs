@@ -0,0 +1,847 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqliteConnection;
+
+use crate::{
+    api::{fetch_transactions, handle_notify, TransactionData},
+    auth::{AuthUser, RequestType},
+    db_utils::is_admin,
+    layout::{render_page, Layout},
+    ledger::{
+        change_money_supply, currency_decimals, default_currency, delete_account, get_balances,
+        get_money_supply, raise_currency_decimals, record_transfer, reject_pending_transactions,
+        to_minor_units, Balance, MoneySupply, TransferError, DEFAULT_CURRENCY, TREASURY,
+    },
+    money::{Money, MAX_DECIMALS},
+    render_html,
+    status::TransactionStatus,
+    util::{err_handle, format_timestamp, ApiRequest, RequestTypeEnum},
+    App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/list_users", get(list_users))
+        .route("/freeze_user/:username", post(freeze_user))
+        .route("/unfreeze_user/:username", post(unfreeze_user))
+        .route("/adjust_balance/:username", post(adjust_balance))
+        .route("/delete_user/:username", post(delete_user))
+        .route("/mint", post(mint))
+        .route("/burn", post(burn))
+        .route("/money_supply", get(money_supply))
+        .route("/create_currency", post(create_currency))
+        .route("/set_currency_decimals", post(set_currency_decimals))
+        .route("/set_exchange_rate", post(set_exchange_rate))
+        .route("/list_transactions", get(list_transactions))
+        .route("/audit_log", get(audit_log))
+}
+
+#[derive(Serialize)]
+struct UserEntry {
+    username: String,
+    display_name: String,
+    balances: Vec<Balance>,
+    role: String,
+    frozen: bool,
+}
+
+#[derive(Serialize)]
+struct AuditEntry {
+    id: i64,
+    admin: String,
+    action: String,
+    target: String,
+    reason: String,
+    timestamp: i64,
+}
+
+#[derive(Deserialize)]
+struct AdminAction {
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct AdjustBalance {
+    /// can be negative to take money away
+    amount: Money,
+    #[serde(default = "default_currency")]
+    currency: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct SupplyChange {
+    amount: Money,
+    #[serde(default = "default_currency")]
+    currency: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct NewCurrency {
+    code: String,
+    name: String,
+    #[serde(default)]
+    decimals: u8,
+}
+
+#[derive(Deserialize)]
+struct CurrencyDecimals {
+    code: String,
+    decimals: u8,
+}
+
+#[derive(Deserialize)]
+struct ExchangeRate {
+    from: String,
+    to: String,
+    /// how much of `to` one whole `from` is worth
+    rate: Money,
+}
+
+#[derive(Deserialize)]
+struct TransactionFilter {
+    user: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+/// Returns the username of the admin or the status code to reject the request with
+pub(crate) async fn require_admin(
+    state: &App,
+    user: Option<(String, String)>,
+) -> Result<String, StatusCode> {
+    let (username, _) = user.ok_or(StatusCode::UNAUTHORIZED)?;
+    match is_admin(state, &username).await {
+        Ok(true) => Ok(username),
+        Ok(false) => Err(StatusCode::FORBIDDEN),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+pub(crate) async fn write_audit_log(
+    conn: &mut SqliteConnection,
+    admin: &str,
+    action: &str,
+    target: &str,
+    reason: &str,
+) -> sqlx::Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO audit_log (admin, action, target, reason, timestamp) VALUES (?,?,?,?,?);",
+        admin,
+        action,
+        target,
+        reason,
+        now
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn fetch_users(conn: &mut SqliteConnection) -> sqlx::Result<Vec<UserEntry>> {
+    let users = sqlx::query!(
+        r#"SELECT username, display_name, role, frozen as "frozen: bool"
+           FROM users ORDER BY username;"#
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut out = Vec::with_capacity(users.len());
+    for u in users {
+        out.push(UserEntry {
+            balances: get_balances(conn, &u.username).await?,
+            username: u.username,
+            display_name: u.display_name,
+            role: u.role,
+            frozen: u.frozen,
+        })
+    }
+    Ok(out)
+}
+
+async fn fetch_audit_log(conn: &mut SqliteConnection, limit: i64) -> sqlx::Result<Vec<AuditEntry>> {
+    sqlx::query_as!(
+        AuditEntry,
+        "SELECT id, admin, action, target, reason, timestamp
+         FROM audit_log ORDER BY id DESC LIMIT ?;",
+        limit
+    )
+    .fetch_all(conn)
+    .await
+}
+
+fn action_done(req_type: RequestTypeEnum, msg: &'static str) -> Response {
+    match req_type {
+        RequestTypeEnum::Json => Json(json!({ "status": msg })).into_response(),
+        RequestTypeEnum::Html => render_html(move || view! { <span>{msg}</span> }),
+    }
+}
+
+async fn list_users(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<UserEntry>>, StatusCode> {
+    require_admin(&state, user).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let users = fetch_users(&mut conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(users))
+}
+
+async fn list_transactions(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Query(filter): Query<TransactionFilter>,
+) -> Result<Json<Vec<TransactionData>>, StatusCode> {
+    require_admin(&state, user).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let transactions = fetch_transactions(&mut conn, filter.user.as_deref(), filter.limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(transactions))
+}
+
+async fn audit_log(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Query(filter): Query<TransactionFilter>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    require_admin(&state, user).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let entries = fetch_audit_log(&mut conn, filter.limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(entries))
+}
+
+async fn freeze_user(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    Path(username): Path<String>,
+    ApiRequest(data): ApiRequest<AdminAction>,
+) -> Result<Response, StatusCode> {
+    let admin = require_admin(&state, user).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let updated = sqlx::query!(
+        "UPDATE users SET frozen = TRUE WHERE username = ? AND role != 'system';",
+        username
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if updated.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    sqlx::query!("DELETE FROM auth_tokens WHERE username = ?;", username)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let rejected = reject_pending_transactions(&mut tx, &username)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    write_audit_log(&mut tx, &admin, "freeze_user", &username, &data.reason)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for id in rejected {
+        handle_notify(&state, &id, TransactionStatus::Rejected);
+    }
+
+    Ok(action_done(req_type, "frozen"))
+}
+
+async fn unfreeze_user(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    Path(username): Path<String>,
+    ApiRequest(data): ApiRequest<AdminAction>,
+) -> Result<Response, StatusCode> {
+    let admin = require_admin(&state, user).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let updated = sqlx::query!("UPDATE users SET frozen = FALSE WHERE username = ?;", username)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if updated.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    write_audit_log(&mut tx, &admin, "unfreeze_user", &username, &data.reason)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(action_done(req_type, "unfrozen"))
+}
+
+async fn adjust_balance(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    Path(username): Path<String>,
+    ApiRequest(data): ApiRequest<AdjustBalance>,
+) -> Result<Response, StatusCode> {
+    let err = err_handle!(
+        req_type,
+        |err: &'static str| (StatusCode::BAD_REQUEST, Json(json!({ "error": err }))).into_response(),
+        |err: &'static str| render_html(move || view! {
+            <span class="text-red-600">{err}</span>
+        })
+    );
+    let admin = require_admin(&state, user).await?;
+    if data.reason.trim().is_empty() {
+        return Ok(err("A reason is required"));
+    }
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let amount = match to_minor_units(&mut tx, &data.currency, data.amount).await {
+        Ok(0) => return Ok(err("Amount can't be 0")),
+        Ok(amount) => amount,
+        Err(TransferError::UnknownCurrency) => return Ok(err("Unknown currency")),
+        Err(TransferError::InvalidAmount(_)) => return Ok(err("Invalid amount for this currency")),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+    // adjustments go through the treasury so the money supply still adds up
+    let transfer = if amount >= 0 {
+        record_transfer(
+            &mut tx,
+            TREASURY,
+            &username,
+            "Balance adjustment",
+            &data.currency,
+            amount,
+        )
+        .await
+    } else {
+        record_transfer(
+            &mut tx,
+            &username,
+            TREASURY,
+            "Balance adjustment",
+            &data.currency,
+            -amount,
+        )
+        .await
+    };
+    match transfer {
+        Ok(_) => {}
+        Err(TransferError::InsufficientFunds) if amount >= 0 => {
+            return Ok(err("The treasury doesn't have enough money, mint some first"))
+        }
+        Err(TransferError::InsufficientFunds) => {
+            return Ok(err("Balance would become negative"))
+        }
+        Err(TransferError::UnknownAccount) => return Ok(err("User not found")),
+        Err(TransferError::UnknownCurrency) => return Ok(err("Unknown currency")),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    let sign = if amount >= 0 { "+" } else { "" };
+    let action = format!("adjust_balance {sign}{} {}", data.amount, data.currency);
+    write_audit_log(&mut tx, &admin, &action, &username, data.reason.trim())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([username.as_str()]);
+
+    Ok(action_done(req_type, "adjusted"))
+}
+
+async fn delete_user(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    Path(username): Path<String>,
+    ApiRequest(data): ApiRequest<AdminAction>,
+) -> Result<Response, StatusCode> {
+    let admin = require_admin(&state, user).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let rejected = match delete_account(&mut tx, &username).await {
+        Ok(rejected) => rejected,
+        Err(TransferError::UnknownAccount) => Err(StatusCode::NOT_FOUND)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+    write_audit_log(&mut tx, &admin, "delete_user", &username, &data.reason)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for id in rejected {
+        handle_notify(&state, &id, TransactionStatus::Rejected);
+    }
+
+    Ok(action_done(req_type, "deleted"))
+}
+
+async fn money_supply(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<MoneySupply>>, StatusCode> {
+    require_admin(&state, user).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let supply = get_money_supply(&mut conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(supply))
+}
+
+async fn mint(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<SupplyChange>,
+) -> Result<Response, StatusCode> {
+    change_supply(
+        state,
+        req_type,
+        user,
+        data.amount,
+        data.currency,
+        data.reason,
+    )
+    .await
+}
+
+async fn burn(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<SupplyChange>,
+) -> Result<Response, StatusCode> {
+    change_supply(
+        state,
+        req_type,
+        user,
+        -data.amount,
+        data.currency,
+        data.reason,
+    )
+    .await
+}
+
+async fn change_supply(
+    state: App,
+    req_type: RequestTypeEnum,
+    user: Option<(String, String)>,
+    amount: Money,
+    currency: String,
+    reason: String,
+) -> Result<Response, StatusCode> {
+    let err = err_handle!(
+        req_type,
+        |err: &'static str| (StatusCode::BAD_REQUEST, Json(json!({ "error": err }))).into_response(),
+        |err: &'static str| render_html(move || view! {
+            <span class="text-red-600">{err}</span>
+        })
+    );
+    let admin = require_admin(&state, user).await?;
+    if reason.trim().is_empty() {
+        return Ok(err("A reason is required"));
+    }
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let minor = match to_minor_units(&mut tx, &currency, amount).await {
+        Ok(0) => return Ok(err("Amount can't be 0")),
+        Ok(minor) => minor,
+        Err(TransferError::UnknownCurrency) => return Ok(err("Unknown currency")),
+        Err(TransferError::InvalidAmount(_)) => return Ok(err("Invalid amount for this currency")),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+    match change_money_supply(&mut tx, &admin, &currency, minor, reason.trim()).await {
+        Ok(_) => {}
+        Err(TransferError::InsufficientFunds) => {
+            return Ok(err("The treasury doesn't have that much money"))
+        }
+        Err(TransferError::UnknownCurrency) => return Ok(err("Unknown currency")),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    let action = if minor > 0 {
+        format!("mint {amount} {currency}")
+    } else {
+        format!("burn {} {currency}", -amount)
+    };
+    write_audit_log(&mut tx, &admin, &action, TREASURY, reason.trim())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(action_done(
+        req_type,
+        if minor > 0 { "minted" } else { "burned" },
+    ))
+}
+
+async fn create_currency(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<NewCurrency>,
+) -> Result<Response, StatusCode> {
+    let admin = require_admin(&state, user).await?;
+    let code = data.code.trim().to_uppercase();
+    if !(1..=8).contains(&code.len()) || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    if data.decimals > MAX_DECIMALS {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query!(
+        "INSERT INTO currencies (code, name, decimals) VALUES (?,?,?);",
+        code,
+        data.name,
+        data.decimals
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::CONFLICT)?;
+    write_audit_log(&mut tx, &admin, "create_currency", &code, &data.name)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(action_done(req_type, "created"))
+}
+
+async fn set_currency_decimals(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<CurrencyDecimals>,
+) -> Result<Response, StatusCode> {
+    let admin = require_admin(&state, user).await?;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let old = currency_decimals(&mut tx, &data.code).await?;
+    raise_currency_decimals(&mut tx, &data.code, data.decimals).await?;
+    let change = format!("{old} -> {}", data.decimals);
+    write_audit_log(
+        &mut tx,
+        &admin,
+        "set_currency_decimals",
+        &data.code,
+        &change,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(action_done(req_type, "decimals set"))
+}
+
+async fn set_exchange_rate(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<ExchangeRate>,
+) -> Result<Response, StatusCode> {
+    let admin = require_admin(&state, user).await?;
+    if data.rate.minor() < 0 || data.from == data.to {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for currency in [&data.from, &data.to] {
+        currency_decimals(&mut tx, currency).await?;
+    }
+    // a rate of 0 disables exchanging between the two
+    if data.rate.minor() == 0 {
+        sqlx::query!(
+            "DELETE FROM exchange_rates WHERE from_currency = ? AND to_currency = ?;",
+            data.from,
+            data.to
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    } else {
+        let (rate, rate_decimals) = (data.rate.minor(), data.rate.decimals());
+        sqlx::query!(
+            "INSERT INTO exchange_rates (from_currency, to_currency, rate, rate_decimals)
+             VALUES (?,?,?,?)
+             ON CONFLICT (from_currency, to_currency)
+             DO UPDATE SET rate = excluded.rate, rate_decimals = excluded.rate_decimals;",
+            data.from,
+            data.to,
+            rate,
+            rate_decimals
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    let target = format!("{}->{}", data.from, data.to);
+    write_audit_log(
+        &mut tx,
+        &admin,
+        "set_exchange_rate",
+        &target,
+        &data.rate.to_string(),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(action_done(req_type, "rate set"))
+}
+
+pub async fn admin_page(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Html<String>, StatusCode> {
+    let admin = require_admin(&state, user).await?;
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let users = fetch_users(&mut conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let transactions = fetch_transactions(&mut conn, None, 50)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let audit_log = fetch_audit_log(&mut conn, 50)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let supply = get_money_supply(&mut conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let html = render_page(move || {
+        let users = users
+            .into_iter()
+            .map(|u| {
+                let freeze = if u.frozen {
+                    format!("/api/admin/unfreeze_user/{}", u.username)
+                } else {
+                    format!("/api/admin/freeze_user/{}", u.username)
+                };
+                let freeze_label = if u.frozen { "Unfreeze" } else { "Freeze" };
+                let adjust = format!("/api/admin/adjust_balance/{}", u.username);
+                let delete = format!("/api/admin/delete_user/{}", u.username);
+                let balances = u
+                    .balances
+                    .iter()
+                    .map(|b| format!("{} {}", b.amount, b.currency))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                view! {
+                    <tr>
+                        <td>{u.username}</td>
+                        <td>{u.display_name}</td>
+                        <td>{balances}</td>
+                        <td>{u.role}</td>
+                        <td>
+                            <form hx-post=freeze hx-swap="outerHTML">
+                                <input type="text" name="reason" placeholder="Reason"/>
+                                <button class="button">{freeze_label}</button>
+                            </form>
+                        </td>
+                        <td>
+                            <form hx-post=adjust hx-swap="outerHTML">
+                                <input type="number" name="amount" placeholder="Amount" step="any"/>
+                                <input type="text" name="currency" value=DEFAULT_CURRENCY size="6"/>
+                                <input type="text" name="reason" placeholder="Reason" required/>
+                                <button class="button">Adjust</button>
+                            </form>
+                        </td>
+                        <td>
+                            <form
+                                hx-post=delete
+                                hx-swap="outerHTML"
+                                hx-confirm="Delete this account? Its money goes to the treasury."
+                            >
+                                <input type="text" name="reason" placeholder="Reason"/>
+                                <button class="button">Delete</button>
+                            </form>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect_view();
+        let transactions = transactions
+            .into_iter()
+            .map(|t| {
+                view! {
+                    <tr>
+                        <td>{t.id}</td>
+                        <td>{t.buyer}</td>
+                        <td>{t.seller}</td>
+                        <td>{t.name}</td>
+                        <td>{t.amount.to_string()}" "{t.currency}</td>
+                        <td>{t.status.as_str()}</td>
+                        <td>{format_timestamp(t.timestamp)}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+        let supply = supply
+            .into_iter()
+            .map(|s| {
+                view! {
+                    <tr>
+                        <td>{s.currency}</td>
+                        <td>{s.total.to_string()}</td>
+                        <td>{s.treasury.to_string()}</td>
+                        <td>{s.issued.to_string()}</td>
+                        <td class:text-red-600=!s.reconciles>
+                            {if s.reconciles { "Reconciles" } else { "Does NOT reconcile!" }}
+                        </td>
+                    </tr>
+                }
+            })
+            .collect_view();
+        let audit_log = audit_log
+            .into_iter()
+            .map(|e| {
+                view! {
+                    <tr>
+                        <td>{format_timestamp(e.timestamp)}</td>
+                        <td>{e.admin}</td>
+                        <td>{e.action}</td>
+                        <td>{e.target}</td>
+                        <td>{e.reason}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        view! {
+            <Layout title="Admin" user=Some(admin)>
+                <h1>Admin</h1>
+                <h2>Money Supply</h2>
+                <table>
+                    <tr>
+                        <th>Currency</th><th>Total</th><th>Treasury</th><th>Issued</th><th></th>
+                    </tr>
+                    {supply}
+                </table>
+                <form hx-post="/api/admin/mint" hx-swap="outerHTML">
+                    <input type="number" name="amount" placeholder="Amount" min="0" step="any"/>
+                    <input type="text" name="currency" value=DEFAULT_CURRENCY size="6"/>
+                    <input type="text" name="reason" placeholder="Reason" required/>
+                    <button class="button">Mint</button>
+                </form>
+                <form hx-post="/api/admin/burn" hx-swap="outerHTML">
+                    <input type="number" name="amount" placeholder="Amount" min="0" step="any"/>
+                    <input type="text" name="currency" value=DEFAULT_CURRENCY size="6"/>
+                    <input type="text" name="reason" placeholder="Reason" required/>
+                    <button class="button">Burn</button>
+                </form>
+                <h2>Currencies</h2>
+                <form hx-post="/api/admin/create_currency" hx-swap="outerHTML">
+                    <input type="text" name="code" placeholder="Code" size="6" required/>
+                    <input type="text" name="name" placeholder="Name" required/>
+                    <input type="number" name="decimals" placeholder="Decimals" min="0" max="8"/>
+                    <button class="button">Create Currency</button>
+                </form>
+                <form hx-post="/api/admin/set_currency_decimals" hx-swap="outerHTML">
+                    <input type="text" name="code" value=DEFAULT_CURRENCY size="6" required/>
+                    <input type="number" name="decimals" placeholder="Decimals" min="1" max="8" required/>
+                    <button class="button">Raise Decimals</button>
+                </form>
+                <form hx-post="/api/admin/set_exchange_rate" hx-swap="outerHTML">
+                    <input type="text" name="from" placeholder="From" size="6" required/>
+                    <input type="text" name="to" placeholder="To" size="6" required/>
+                    <input type="number" name="rate" placeholder="Rate" step="any" min="0" required/>
+                    <button class="button">Set Exchange Rate</button>
+                </form>
+                <h2>Users</h2>
+                <table>
+                    <tr><th>Username</th><th>Display Name</th><th>Money</th><th>Role</th></tr>
+                    {users}
+                </table>
+                <h2>Transactions</h2>
+                <table>
+                    <tr>
+                        <th>Id</th><th>Buyer</th><th>Seller</th><th>Name</th>
+                        <th>Amount</th><th>Status</th><th>Time</th>
+                    </tr>
+                    {transactions}
+                </table>
+                <h2>Audit Log</h2>
+                <table>
+                    <tr><th>Time</th><th>Admin</th><th>Action</th><th>Target</th><th>Reason</th></tr>
+                    {audit_log}
+                </table>
+            </Layout>
+        }
+    });
+
+    Ok(html)
+}
@@ -1,18 +1,33 @@
 use axum::{
-    body::Body,
-    extract::{ws::Message, Path, State, WebSocketUpgrade},
+    extract::{Path, State, WebSocketUpgrade},
     http::StatusCode,
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
     Json, Router,
 };
-use serde::Deserialize;
-use serde_json::json;
-use sqlx::{query, Acquire};
+use leptos::view;
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, SqliteConnection, Transaction};
 
 use crate::{
     auth::{AuthUser, RequestType},
-    util::{get_displayname_from_valid_auth_token, get_random_string, ApiRequest},
+    catalog::{get_product, restock, take_stock},
+    db_utils::is_frozen,
+    events::{forward_status, Event},
+    invoices::{build_lines, save_lines, FeeLine, LineItem, TaxLine},
+    ledger::{
+        convert_currency, currency_decimals, default_currency, expire_stale_transactions,
+        get_balances, get_transaction_ttl, move_money, record_transfer, set_status, to_minor_units,
+        Balance,
+    },
+    money::Money,
+    notifications::notify_user,
+    receipts::{get_receipt, verify_receipt},
+    status::TransactionStatus,
+    util::{
+        describe_status, get_displayname_from_valid_auth_token, get_random_string, render_html,
+        ApiRequest, RequestTypeEnum,
+    },
     App,
 };
 
@@ -20,40 +35,170 @@ pub fn get_router() -> Router<App> {
     Router::new()
         .route("/get_displayname", post(get_displayname))
         .route("/request_transaction", post(request_transaction))
+        .route("/send_money", post(send_money))
         .route("/accept_transaction/:id", post(accept_transaction))
         .route("/reject_transaction/:id", post(reject_transaction))
-        .route("/notify_transaction/:id", post(notify_transaction))
+        .route("/cancel_transaction/:id", post(cancel_transaction))
+        .route("/refund_transaction/:id", post(refund_transaction))
+        .route("/notify_transaction/:id", get(notify_transaction))
+        .route("/receipt/:id", post(get_receipt))
+        .route("/verify_receipt", post(verify_receipt))
+        .route("/get_balances", post(get_balances_handler))
+        .route("/currencies", get(currencies))
+        .route("/convert_currency", post(convert_currency_handler))
 }
 
 #[derive(Deserialize, Debug)]
 struct RequestTransaction {
     buyer: String,
-    name: String,
-    amount: i32,
+    /// a product from the sellers catalog, its title, price and currency are used instead of the ones below
+    sku: Option<String>,
+    name: Option<String>,
+    amount: Option<Money>,
+    #[serde(default = "default_currency")]
+    currency: String,
+    /// makes it an invoice, the amount is what the lines add up to and
+    /// the name defaults to the first item
+    #[serde(default)]
+    items: Vec<LineItem>,
+    #[serde(default)]
+    taxes: Vec<TaxLine>,
+    #[serde(default)]
+    fees: Vec<FeeLine>,
 }
 
-struct TransactionData {
-    buyer: String,
-    seller: String,
+#[derive(Deserialize, Debug)]
+struct SendMoney {
+    to: String,
+    amount: Money,
+    #[serde(default = "default_currency")]
+    currency: String,
+    #[serde(default)]
+    memo: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConvertCurrency {
+    from: String,
+    to: String,
+    amount: Money,
+}
+
+#[derive(Serialize, Debug)]
+struct Currency {
+    code: String,
     name: String,
-    amount: i64,
-    /// 0 = waiting, 1 = accepted, 2 = rejected
-    accepted: i8,
-    timestamp: i64,
 }
 
-async fn handle_notify(state: &App, id: &str, accepted: bool) {
-    let msg = if accepted {
-        "transaction_accepted"
-    } else {
-        "transaction_rejected"
+#[derive(Serialize, Debug)]
+pub(crate) struct TransactionData {
+    pub id: String,
+    pub buyer: String,
+    pub seller: String,
+    pub name: String,
+    pub amount: Money,
+    pub currency: String,
+    pub status: TransactionStatus,
+    pub timestamp: i64,
+}
+
+/// Newest first, only the ones `user` took part in if given
+pub(crate) async fn fetch_transactions(
+    conn: &mut SqliteConnection,
+    user: Option<&str>,
+    limit: i64,
+) -> sqlx::Result<Vec<TransactionData>> {
+    let rows = sqlx::query!(
+        r#"SELECT id, buyer, seller, transactions.name, amount, currency, decimals,
+            status as "status: TransactionStatus", timestamp
+           FROM transactions INNER JOIN currencies ON code = currency
+           WHERE ?1 IS NULL OR buyer = ?1 OR seller = ?1
+           ORDER BY timestamp DESC LIMIT ?2;"#,
+        user,
+        limit
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| TransactionData {
+            id: r.id,
+            buyer: r.buyer,
+            seller: r.seller,
+            name: r.name,
+            amount: Money::new(r.amount, r.decimals as u8),
+            currency: r.currency,
+            status: r.status,
+            timestamp: r.timestamp,
+        })
+        .collect())
+}
+
+/// Pending transactions waiting on `buyer` that can still be answered, oldest first
+pub(crate) async fn fetch_pending(
+    conn: &mut SqliteConnection,
+    buyer: &str,
+) -> sqlx::Result<Vec<TransactionData>> {
+    let cutoff = (chrono::Utc::now() - get_transaction_ttl()).timestamp();
+    let rows = sqlx::query!(
+        r#"SELECT id, buyer, seller, transactions.name, amount, currency, decimals,
+            status as "status: TransactionStatus", timestamp
+           FROM transactions INNER JOIN currencies ON code = currency
+           WHERE buyer = ? AND status = ? AND timestamp >= ?
+           ORDER BY timestamp;"#,
+        buyer,
+        TransactionStatus::Pending,
+        cutoff
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| TransactionData {
+            id: r.id,
+            buyer: r.buyer,
+            seller: r.seller,
+            name: r.name,
+            amount: Money::new(r.amount, r.decimals as u8),
+            currency: r.currency,
+            status: r.status,
+            timestamp: r.timestamp,
+        })
+        .collect())
+}
+
+/// Expires transactions nobody answered in time
+pub(crate) async fn expire_transactions(state: &App) {
+    let ids = match state.db.acquire().await {
+        Ok(mut conn) => expire_stale_transactions(&mut conn).await,
+        Err(err) => Err(err),
     };
-    if let Some(mut socket) = state.transaction_notif_sockets.lock().await.remove(id) {
-        _ = socket.send(Message::Text(msg.to_owned())).await;
-        _ = socket.close().await;
+    match ids {
+        Ok((ids, users)) => {
+            for id in ids {
+                handle_notify(state, &id, TransactionStatus::Expired);
+            }
+            if !users.is_empty() {
+                state.events.changed(users);
+            }
+        }
+        Err(err) => println!("failed to expire transactions: {err}"),
     }
 }
 
+/// Tells whoever waits on the transaction socket that it's done, as "transaction_{status}"
+pub(crate) fn handle_notify(state: &App, id: &str, status: TransactionStatus) {
+    send_notification(state, id, format!("transaction_{status}"));
+}
+
+/// Sends `msg` to the sockets waiting on `id` and closes them
+pub(crate) fn send_notification(state: &App, id: &str, msg: String) {
+    state.events.publish(Event::Status {
+        id: id.to_owned(),
+        msg,
+    });
+}
+
 async fn notify_transaction(
     State(state): State<App>,
     AuthUser(user): AuthUser,
@@ -65,11 +210,11 @@ async fn notify_transaction(
         None => Err(StatusCode::UNAUTHORIZED)?,
     };
     let can_read_status = sqlx::query!(
-        "SELECT true FROM transactions WHERE seller = ? OR buyer = ?;",
-        user,
+        "SELECT buyer FROM transactions WHERE id = ?1 AND (seller = ?2 OR buyer = ?2);",
+        transaction_id,
         user
     )
-    .fetch_one(
+    .fetch_optional(
         &mut *state
             .db
             .acquire()
@@ -77,20 +222,92 @@ async fn notify_transaction(
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
     )
     .await
-    .is_ok_and(|o| o.r#true == 1);
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .is_some();
     if !can_read_status {
         Err(StatusCode::UNAUTHORIZED)?;
     }
-    let out = ws.on_upgrade(move |socket| async move {
-        state
-            .transaction_notif_sockets
-            .lock()
-            .await
-            .insert(transaction_id, socket);
-    });
+    // listen before upgrading so nothing that happens in between gets missed
+    let events = state.events.subscribe();
+    let out = ws.on_upgrade(move |socket| forward_status(socket, events, transaction_id));
     Ok(out)
 }
 
+/// The parts of a transaction the status changes need
+struct StoredTransaction {
+    buyer: String,
+    seller: String,
+    amount: i64,
+    currency: String,
+    status: TransactionStatus,
+    timestamp: i64,
+    sku: Option<String>,
+}
+
+impl StoredTransaction {
+    fn is_stale(&self) -> bool {
+        let cutoff = (chrono::Utc::now() - get_transaction_ttl()).timestamp();
+        self.status == TransactionStatus::Pending && self.timestamp < cutoff
+    }
+}
+
+async fn fetch_transaction(
+    conn: &mut SqliteConnection,
+    id: &str,
+) -> Result<StoredTransaction, StatusCode> {
+    sqlx::query_as!(
+        StoredTransaction,
+        r#"SELECT buyer, seller, amount, currency,
+            status as "status: TransactionStatus", timestamp, sku
+           FROM transactions WHERE id = ?;"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Marks the transaction as expired and tells the seller, answers with 410
+async fn expire_transaction(
+    state: &App,
+    mut tx: Transaction<'_, Sqlite>,
+    id: &str,
+) -> Result<Response, StatusCode> {
+    set_status(
+        &mut tx,
+        id,
+        TransactionStatus::Pending,
+        TransactionStatus::Expired,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    handle_notify(state, id, TransactionStatus::Expired);
+    Err(StatusCode::GONE)
+}
+
+/// What a button on the page gets back, htmx doesn't swap in error responses
+/// so those become a message too
+fn answer(
+    req_type: RequestTypeEnum,
+    result: Result<Response, StatusCode>,
+    done: &'static str,
+) -> Result<Response, StatusCode> {
+    match req_type {
+        RequestTypeEnum::Json => result,
+        RequestTypeEnum::Html => {
+            let msg = match result {
+                Ok(_) => done,
+                Err(code) => describe_status(code),
+            };
+            Ok(render_html(move || view! { <span>{msg}</span> }))
+        }
+    }
+}
+
+/// buyer pays the seller
 async fn accept_transaction(
     State(state): State<App>,
     RequestType(req_type): RequestType,
@@ -101,36 +318,66 @@ async fn accept_transaction(
         Some((name, _)) => name,
         None => Err(StatusCode::UNAUTHORIZED)?,
     };
-    let mut conn = match state.db.acquire().await {
-        Ok(conn) => conn,
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
-    };
-    let exsits = sqlx::query!(
-        "SELECT true FROM transactions WHERE id= ? AND buyer = ?;",
-        transaction_id,
-        user
+    answer(
+        req_type,
+        accept(&state, &user, &transaction_id).await,
+        "Paid",
     )
-    .fetch_one(&mut *conn)
-    .await
-    .is_ok_and(|r| r.r#true == 1);
+}
 
-    if !exsits {
+async fn accept(state: &App, user: &str, transaction_id: &str) -> Result<Response, StatusCode> {
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+    let transaction = fetch_transaction(&mut tx, transaction_id).await?;
+    if transaction.buyer != user {
         Err(StatusCode::NOT_FOUND)?;
     }
-    sqlx::query!(
-        "UPDATE transactions SET accepted = 1 WHERE id = ? AND buyer = ?;",
+    if transaction.is_stale() {
+        return expire_transaction(state, tx, transaction_id).await;
+    }
+    if !transaction.status.can_become(TransactionStatus::Accepted) {
+        Err(StatusCode::CONFLICT)?;
+    }
+    // the seller might have been frozen or deleted since requesting the transaction
+    if !matches!(is_frozen(state, &transaction.seller).await, Ok(false)) {
+        Err(StatusCode::FORBIDDEN)?;
+    }
+    move_money(
+        &mut tx,
+        user,
+        &transaction.seller,
+        &transaction.currency,
+        transaction.amount,
+    )
+    .await?;
+    if let Some(sku) = &transaction.sku {
+        let in_stock = take_stock(&mut tx, &transaction.seller, sku)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !in_stock {
+            Err(StatusCode::CONFLICT)?;
+        }
+    }
+    set_status(
+        &mut tx,
         transaction_id,
-        user
+        transaction.status,
+        TransactionStatus::Accepted,
     )
-    .execute(&mut *conn)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    handle_notify(&state, &transaction_id, true).await;
+    handle_notify(state, transaction_id, TransactionStatus::Accepted);
+    state.events.changed([user, &transaction.seller]);
 
     Ok(StatusCode::OK.into_response())
 }
 
+/// buyer says no
 async fn reject_transaction(
     State(state): State<App>,
     RequestType(req_type): RequestType,
@@ -141,32 +388,124 @@ async fn reject_transaction(
         Some((name, _)) => name,
         None => Err(StatusCode::UNAUTHORIZED)?,
     };
+    answer(
+        req_type,
+        reject(&state, &user, &transaction_id).await,
+        "Rejected",
+    )
+}
 
-    let mut conn = match state.db.acquire().await {
-        Ok(conn) => conn,
+async fn reject(state: &App, user: &str, transaction_id: &str) -> Result<Response, StatusCode> {
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
         Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
     };
-    let exsits = sqlx::query!(
-        "SELECT true FROM transactions WHERE id= ? AND buyer = ?;",
+    let transaction = fetch_transaction(&mut tx, transaction_id).await?;
+    if transaction.buyer != user {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    if transaction.is_stale() {
+        return expire_transaction(state, tx, transaction_id).await;
+    }
+    set_status(
+        &mut tx,
         transaction_id,
-        user
+        transaction.status,
+        TransactionStatus::Rejected,
     )
-    .fetch_one(&mut *conn)
-    .await
-    .is_ok_and(|r| r.r#true == 1);
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    handle_notify(state, transaction_id, TransactionStatus::Rejected);
+    state.events.changed([user, &transaction.seller]);
+
+    Ok(StatusCode::OK.into_response())
+}
+
+/// seller takes back a request the buyer didn't answer yet
+async fn cancel_transaction(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(transaction_id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+    let transaction = fetch_transaction(&mut tx, &transaction_id).await?;
+    if transaction.seller != user {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    if transaction.is_stale() {
+        return expire_transaction(&state, tx, &transaction_id).await;
+    }
+    set_status(
+        &mut tx,
+        &transaction_id,
+        transaction.status,
+        TransactionStatus::Cancelled,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    handle_notify(&state, &transaction_id, TransactionStatus::Cancelled);
+    state.events.changed([&transaction.buyer, &user]);
+
+    Ok(StatusCode::OK.into_response())
+}
 
-    if !exsits {
+/// seller gives the money of an accepted transaction back
+async fn refund_transaction(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(transaction_id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+    let transaction = fetch_transaction(&mut tx, &transaction_id).await?;
+    if transaction.seller != user {
         Err(StatusCode::NOT_FOUND)?;
     }
-    sqlx::query!(
-        "UPDATE transactions SET accepted = 2 WHERE id = ? AND buyer = ?;",
-        transaction_id,
-        user
+    if !transaction.status.can_become(TransactionStatus::Refunded) {
+        Err(StatusCode::CONFLICT)?;
+    }
+    move_money(
+        &mut tx,
+        &user,
+        &transaction.buyer,
+        &transaction.currency,
+        transaction.amount,
     )
-    .execute(&mut *conn)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    handle_notify(&state, &transaction_id, false).await;
+    .await?;
+    if let Some(sku) = &transaction.sku {
+        restock(&mut tx, &user, sku)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    set_status(
+        &mut tx,
+        &transaction_id,
+        transaction.status,
+        TransactionStatus::Refunded,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    handle_notify(&state, &transaction_id, TransactionStatus::Refunded);
+    state.events.changed([&transaction.buyer, &user]);
 
     Ok(StatusCode::OK.into_response())
 }
@@ -180,30 +519,205 @@ async fn request_transaction(
         Some((name, _)) => name,
         None => Err(StatusCode::UNAUTHORIZED)?,
     };
+    match is_frozen(&state, &user).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    match is_frozen(&state, &data.buyer).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::NOT_FOUND)?,
+    }
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let is_invoice = !data.items.is_empty();
+    let has_extras = !data.taxes.is_empty() || !data.fees.is_empty();
+    if is_invoice && (data.sku.is_some() || data.amount.is_some()) || !is_invoice && has_extras {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    let mut lines = Vec::new();
+    let (name, amount, currency) = match &data.sku {
+        None if is_invoice => {
+            let name = match data.name {
+                Some(name) => name,
+                None => data.items[0].description.clone(),
+            };
+            let decimals = currency_decimals(&mut conn, &data.currency).await?;
+            let (built, total) = build_lines(decimals, data.items, data.taxes, data.fees)?;
+            lines = built;
+            (name, total, data.currency)
+        }
+        Some(sku) => {
+            let product = get_product(&mut conn, &user, sku)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+            if product.stock <= 0 {
+                Err(StatusCode::CONFLICT)?;
+            }
+            (product.title, product.price, product.currency)
+        }
+        None => {
+            let (Some(name), Some(amount)) = (data.name, data.amount) else {
+                Err(StatusCode::BAD_REQUEST)?
+            };
+            if !amount.is_positive() {
+                Err(StatusCode::BAD_REQUEST)?;
+            }
+            let amount = to_minor_units(&mut conn, &data.currency, amount).await?;
+            (name, amount, data.currency)
+        }
+    };
+    drop(conn);
     let now = chrono::Utc::now().timestamp();
     let id = get_random_string(8);
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     sqlx::query!(
-        "INSERT INTO transactions VALUES (?,?,?,?,?,0,?)",
+        "INSERT INTO transactions (id, buyer, seller, name, amount, status, timestamp, currency, sku)
+         VALUES (?,?,?,?,?,?,?,?,?)",
         id,
         data.buyer,
         user,
-        data.name,
-        data.amount,
-        now
-    )
-    .execute(
-        &mut *state
-            .db
-            .acquire()
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        name,
+        amount,
+        TransactionStatus::Pending,
+        now,
+        currency,
+        data.sku
     )
+    .execute(&mut *tx)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    save_lines(&mut tx, &id, &lines)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([&data.buyer, &user]);
 
     Ok((StatusCode::OK, Json(id)))
 }
 
+/// Pays another user right away, no request needed
+async fn send_money(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<SendMoney>,
+) -> Result<Response, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let result = transfer(&state, &user, data)
+        .await
+        .map(|id| Json(id).into_response());
+    answer(req_type, result, "Sent")
+}
+
+async fn transfer(state: &App, user: &str, data: SendMoney) -> Result<String, StatusCode> {
+    let to = data.to.to_lowercase();
+    if !data.amount.is_positive() || to == user {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    for name in [user, to.as_str()] {
+        match is_frozen(state, name).await {
+            Ok(false) => {}
+            Ok(true) => Err(StatusCode::FORBIDDEN)?,
+            Err(_) => Err(StatusCode::NOT_FOUND)?,
+        }
+    }
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let amount = to_minor_units(&mut tx, &data.currency, data.amount).await?;
+    let memo = if data.memo.is_empty() {
+        format!("Transfer from {user}")
+    } else {
+        data.memo
+    };
+    let id = record_transfer(&mut tx, user, &to, &memo, &data.currency, amount).await?;
+    let msg = format!("{user} sent you {} {}", data.amount, data.currency);
+    notify_user(&mut tx, &to, &msg)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([user, &to]);
+    Ok(id)
+}
+
+async fn get_balances_handler(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<Balance>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let balances = get_balances(&mut conn, &user)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(balances))
+}
+
+async fn currencies(State(state): State<App>) -> Result<Json<Vec<Currency>>, StatusCode> {
+    let currencies = sqlx::query_as!(Currency, "SELECT code, name FROM currencies ORDER BY code;")
+        .fetch_all(
+            &mut *state
+                .db
+                .acquire()
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(currencies))
+}
+
+/// Exchanges the users own money into another currency at the admin set rate
+async fn convert_currency_handler(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<ConvertCurrency>,
+) -> Result<Json<Money>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    if !data.amount.is_positive() {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let received = convert_currency(&mut tx, &user, &data.from, &data.to, data.amount).await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([user.as_str()]);
+    Ok(Json(received))
+}
+
 #[derive(Deserialize)]
 struct UsingToken {
     request_token: String,
@@ -0,0 +1,58 @@
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+/// A file built into the binary, see build.rs
+pub struct Asset {
+    /// like "htmx.min.js"
+    pub name: &'static str,
+    /// like "htmx.min.0123456789abcdef.js", changes whenever the file does
+    pub hashed_name: &'static str,
+    pub hash: &'static str,
+    pub content_type: &'static str,
+    pub body: &'static [u8],
+}
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+fn find(name: &str) -> Option<&'static Asset> {
+    ASSETS.iter().find(|a| a.name == name)
+}
+
+/// Where to link an asset from, `None` if it wasn't there at build time
+pub fn asset_url(name: &str) -> Option<String> {
+    find(name).map(|a| format!("/assets/{}", a.hashed_name))
+}
+
+/// Hashed names never change so browsers can keep them forever,
+/// plain names have to be checked against the ETag every time
+pub async fn serve_asset(Path(file): Path<String>, headers: HeaderMap) -> Response {
+    let (asset, cache) = match ASSETS.iter().find(|a| a.hashed_name == file) {
+        Some(asset) => (asset, "public, max-age=31536000, immutable"),
+        None => match find(&file) {
+            Some(asset) => (asset, "no-cache"),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+    };
+    let etag = format!("\"{}\"", asset.hash);
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| h.split(',').any(|t| t.trim() == etag || t.trim() == "*"));
+    let head = [
+        (header::ETAG, etag),
+        (header::CACHE_CONTROL, cache.to_owned()),
+    ];
+    if cached {
+        (StatusCode::NOT_MODIFIED, head).into_response()
+    } else {
+        (
+            head,
+            [(header::CONTENT_TYPE, asset.content_type)],
+            asset.body,
+        )
+            .into_response()
+    }
+}
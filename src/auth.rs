@@ -3,34 +3,59 @@ use std::{fmt::Debug, ops::Add};
 use axum::{
     async_trait,
     extract::{FromRef, FromRequestParts, State},
-    http::{request::Parts, StatusCode},
-    response::{IntoResponse, Response},
-    routing::post,
+    http::{request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
     Json, RequestPartsExt, Router,
 };
 use axum_extra::extract::{cookie::Cookie, CookieJar};
 use chrono::Duration;
 use leptos::*;
 use serde_json::json;
-use totp_rs::Secret;
+use totp_rs::{Secret, TOTP};
 
 use crate::{
+    api::handle_notify,
+    config,
     db_utils::get_displayname_from_username,
-    get_otp, render_html,
-    util::{err_handle, get_requested_type, render_html_into_body, ApiRequest, RequestTypeEnum},
-    App, Base64Image, LoginForm, RegisterForm,
+    get_otp,
+    layout::{go_to, render_page, set_flash, take_flash, Layout},
+    ledger::{
+        delete_account as delete_account_in_db, record_transfer, to_minor_units, TransferError,
+        DEFAULT_CURRENCY, TREASURY,
+    },
+    money::Money,
+    render_html,
+    status::TransactionStatus,
+    util::{err_handle, get_requested_type, ApiRequest, RequestTypeEnum},
+    App, Base64Image, DBPool, LoginForm, RegisterForm,
 };
 
 pub const AUTH_IDENT: &str = "Money-Auth-Key";
 pub fn get_auth_token_lifetime() -> Duration {
-    Duration::weeks(1)
+    Duration::days(config::get().ttl.auth_token_days)
+}
+/// Logins a user can have per kind of client, logging in once more logs out the oldest
+pub fn get_max_tokens_per_client() -> i64 {
+    config::get().economy.max_tokens_per_client
 }
 
 pub fn get_router() -> Router<App> {
     Router::new()
-        .route("/register", post(register))
-        .route("/login", post(login))
+        .route("/register", get(register_page).post(register))
+        .route("/login", get(login_page).post(login))
         .route("/logout", post(logout))
+        .route("/delete_account", post(delete_account))
+}
+
+/// What an auth token was made for
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+pub enum ClientType {
+    /// the cookie of a browser
+    Web = 0,
+    /// the `Money-Auth-Key` header of a script or computer
+    Api = 1,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -39,15 +64,54 @@ pub struct LoginData {
     otp: i32,
 }
 
+#[derive(serde::Deserialize, Debug)]
+pub struct DeleteAccountData {
+    otp: i32,
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct RegisterData {
     username: String,
     display_name: String,
 }
 
+async fn login_page(AuthUser(user): AuthUser, jar: CookieJar) -> Response {
+    if user.is_some() {
+        return Redirect::to("/dashboard").into_response();
+    }
+    let (jar, flash) = take_flash(jar);
+    let html = render_page(move || {
+        view! {
+            <Layout title="Login" user=None flash=flash>
+                <h1>Login</h1>
+                <LoginForm/>
+                <p>"No account yet? "<a href="/register">Signup</a></p>
+            </Layout>
+        }
+    });
+    (jar, html).into_response()
+}
+
+async fn register_page(AuthUser(user): AuthUser) -> Response {
+    if user.is_some() {
+        return Redirect::to("/dashboard").into_response();
+    }
+    render_page(|| {
+        view! {
+            <Layout title="Signup" user=None>
+                <h1>Signup</h1>
+                <p>"You log in with a passcode from an authenticator app, scan the code you get after signing up with it"</p>
+                <RegisterForm/>
+            </Layout>
+        }
+    })
+    .into_response()
+}
+
 async fn logout(
     State(state): State<App>,
     cookie_jar: Option<CookieJar>,
+    headers: HeaderMap,
     RequestType(req_type): RequestType,
     AuthUser(auth_data): AuthUser,
 ) -> Response {
@@ -71,30 +135,120 @@ async fn logout(
         .execute(&mut *conn)
         .await;
     if let (RequestTypeEnum::Html, Some(jar)) = (req_type, cookie_jar) {
-        let jar = jar.remove(Cookie::from(AUTH_IDENT));
-        return (StatusCode::OK, jar).into_response();
+        let jar = set_flash(jar.remove(Cookie::from(AUTH_IDENT)), "Logged out");
+        return (jar, go_to(&headers, "/login")).into_response();
     }
 
     StatusCode::OK.into_response()
 }
-async fn gen_token_and_store_in_db(app: &App, username: &str) -> eyre::Result<String> {
+/// Deleting needs a fresh passcode so a stolen token alone can't wipe an account
+async fn delete_account(
+    State(state): State<App>,
+    cookie_jar: Option<CookieJar>,
+    headers: HeaderMap,
+    RequestType(req_type): RequestType,
+    AuthUser(auth_data): AuthUser,
+    ApiRequest(data): ApiRequest<DeleteAccountData>,
+) -> Response {
+    let err = err_handle!(
+        req_type,
+        |err: String| Json(json!({"error":err})).into_response(),
+        |err: String| render_html(|| view! {
+            <div class="text-red-600">{err}</div>
+        })
+    );
+    let (username, _) = match auth_data {
+        Some(d) => d,
+        None => return err("Not logged in".to_string()),
+    };
+    let mut conn = match state.db.acquire().await {
+        Ok(v) => v,
+        Err(e) => return err(e.to_string()),
+    };
+    let secret = match sqlx::query!("SELECT secret FROM users WHERE username = ?;", username)
+        .fetch_one(&mut *conn)
+        .await
+    {
+        Ok(r) => Secret::Encoded(r.secret),
+        Err(e) => return err(e.to_string()),
+    };
+    drop(conn);
+    let otp = get_otp(secret, &username).unwrap();
+    if !otp.check_current(&data.otp.to_string()).unwrap() {
+        return err("Incorect Passcode".to_string());
+    }
+    let mut tx = match state.db.begin().await {
+        Ok(v) => v,
+        Err(e) => return err(e.to_string()),
+    };
+    let rejected = match delete_account_in_db(&mut tx, &username).await {
+        Ok(rejected) => rejected,
+        Err(e) => return err(e.to_string()),
+    };
+    if let Err(e) = tx.commit().await {
+        return err(e.to_string());
+    }
+    for id in rejected {
+        handle_notify(&state, &id, TransactionStatus::Rejected);
+    }
+    if let (RequestTypeEnum::Html, Some(jar)) = (req_type, cookie_jar) {
+        let jar = set_flash(jar.remove(Cookie::from(AUTH_IDENT)), "Account deleted");
+        return (jar, go_to(&headers, "/")).into_response();
+    }
+
+    StatusCode::OK.into_response()
+}
+
+/// Makes a new login, `replaces` is the token this client had before, if any.
+/// Expired tokens and the oldest beyond `get_max_tokens_per_client` of the user get removed.
+async fn gen_token_and_store_in_db(
+    app: &App,
+    username: &str,
+    client: ClientType,
+    replaces: Option<&str>,
+) -> eyre::Result<String> {
     let token = Secret::generate_secret().to_encoded().to_string();
-    let expire_stamp = (chrono::Utc::now() + get_auth_token_lifetime()).timestamp();
+    let now = chrono::Utc::now();
+    let expire_stamp = (now + get_auth_token_lifetime()).timestamp();
+    let now = now.timestamp();
+    let max_tokens = get_max_tokens_per_client();
 
+    let mut tx = app.db.begin().await?;
+    if let Some(old) = replaces {
+        sqlx::query!("DELETE FROM auth_tokens WHERE token = ?;", old)
+            .execute(&mut *tx)
+            .await?;
+    }
     sqlx::query!(
-        "INSERT INTO auth_tokens VALUES (?,?,?)",
+        "INSERT INTO auth_tokens (token, username, expire_timestamp, client, created)
+         VALUES (?,?,?,?,?);",
         token,
         username,
-        expire_stamp
+        expire_stamp,
+        client,
+        now
     )
-    .execute(&mut *app.db.acquire().await?)
+    .execute(&mut *tx)
     .await?;
+    sqlx::query!(
+        "DELETE FROM auth_tokens WHERE username = ?1 AND (expire_timestamp <= ?2 OR (client = ?3
+            AND token NOT IN (SELECT token FROM auth_tokens WHERE username = ?1 AND client = ?3
+                ORDER BY created DESC, rowid DESC LIMIT ?4)));",
+        username,
+        now,
+        client,
+        max_tokens
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
     Ok(token)
 }
 
 async fn login(
     State(state): State<App>,
     cookie_jar: Option<CookieJar>,
+    headers: HeaderMap,
     RequestType(req_type): RequestType,
     ApiRequest(data): ApiRequest<LoginData>,
 ) -> Response {
@@ -111,11 +265,17 @@ async fn login(
         Ok(v) => v,
         Err(e) => return err(e.to_string()),
     };
-    let r = sqlx::query!("SELECT secret FROM users WHERE username = ?;", username)
-        .fetch_one(&mut *conn)
-        .await;
+    let r = sqlx::query!(
+        "SELECT secret, frozen, role FROM users WHERE username = ?;",
+        username
+    )
+    .fetch_one(&mut *conn)
+    .await;
     let otp_secret = match r {
-        Ok(secret) => secret.secret,
+        // system accounts like the treasury have no secret and can't be logged into
+        Ok(r) if r.role == "system" => return err("User Not Found".to_owned()),
+        Ok(r) if r.frozen != 0 => return err("Account Frozen".to_owned()),
+        Ok(r) => r.secret,
         Err(_) => return err("User Not Found".to_owned()),
     };
     let otp_secret = Secret::Encoded(otp_secret);
@@ -134,38 +294,32 @@ async fn login(
                 Some(j) => j,
                 None => return StatusCode::BAD_REQUEST.into_response(),
             };
-            if cookie_jar.get(AUTH_IDENT).is_some() {
-                return err("already logged in!".to_string());
-            }
-            let token = match gen_token_and_store_in_db(&state, &username).await {
-                Ok(token) => token,
-                Err(_) => {
-                    return err("Could not insert new token into db!".to_string());
-                }
-            };
+            // whatever login the browser had, it gets this one instead
+            let old = cookie_jar.get(AUTH_IDENT).map(|c| c.value().to_owned());
+            let token =
+                match gen_token_and_store_in_db(&state, &username, ClientType::Web, old.as_deref())
+                    .await
+                {
+                    Ok(token) => token,
+                    Err(_) => {
+                        return err("Could not insert new token into db!".to_string());
+                    }
+                };
             let mut cookie = Cookie::new(AUTH_IDENT, token.clone());
             cookie.set_http_only(Some(true));
             cookie.set_same_site(Some(axum_extra::extract::cookie::SameSite::Lax));
 
-            let cookie_jar = cookie_jar.add(cookie);
-            (
-                cookie_jar,
-                render_html_into_body(move || {
-                    view! {
-                        <p>Hi {display_name}</p>
-                        <p>otp correct: {correct}</p>
-                    }
-                }),
-            )
-                .into_response()
+            let cookie_jar = set_flash(cookie_jar.add(cookie), &format!("Hi {display_name}"));
+            (cookie_jar, go_to(&headers, "/dashboard")).into_response()
         }
         RequestTypeEnum::Json => {
-            let token = match gen_token_and_store_in_db(&state, &username).await {
-                Ok(token) => token,
-                Err(_) => {
-                    return err("Could not insert new token into db!".to_string());
-                }
-            };
+            let token =
+                match gen_token_and_store_in_db(&state, &username, ClientType::Api, None).await {
+                    Ok(token) => token,
+                    Err(_) => {
+                        return err("Could not insert new token into db!".to_string());
+                    }
+                };
             Json(json!({"auth_token":token})).into_response()
         }
     }
@@ -185,42 +339,173 @@ async fn register(
         })
     );
 
-    if data.username.contains(':') {
-        return err("Username Contains Forbidden \':\' Symbol".to_owned());
+    let account = match create_account(
+        &state.db,
+        &data.username,
+        &data.display_name,
+        "user",
+        state.starting_grant,
+    )
+    .await
+    {
+        Ok(account) => account,
+        Err(e) => return err(e),
     };
-    let username = data.username.to_lowercase();
+    let qr_code = account.otp.get_qr_base64().unwrap();
+    let secret = account.otp.get_secret_base32();
+    render_html(move || {
+        view! {
+            <div>
+                <Base64Image base64=qr_code alt="Qr Code".to_string()/>
+                <p>OTP Secret:{secret}</p>
+                {account.grant_missing.then(|| view! {
+                    <p>"The treasury can't pay your starting grant right now, ask an admin for it"</p>
+                })}
+                <a href="/login" class="button">Continue to login</a>
+            </div>
+        }
+    })
+}
+
+/// What `create_account` made
+pub(crate) struct NewAccount {
+    pub otp: TOTP,
+    /// the treasury couldn't pay the starting grant, the account was made without it
+    pub grant_missing: bool,
+}
+
+/// Adds a user with a new passcode secret and pays them the starting grant if the treasury can.
+/// The errors are meant for whoever is signing up.
+pub(crate) async fn create_account(
+    db: &DBPool,
+    username: &str,
+    display_name: &str,
+    role: &str,
+    starting_grant: Money,
+) -> Result<NewAccount, String> {
+    let username = username.trim().to_lowercase();
+    validate_username(&username)?;
+    let display_name = display_name.trim().to_owned();
+    validate_display_name(&display_name)?;
+    let mut conn = db.acquire().await.map_err(|e| e.to_string())?;
+    let username_taken = sqlx::query!("SELECT true FROM users WHERE username = ?;", username)
+        .fetch_optional(&mut *conn)
+        .await;
+    match username_taken {
+        Ok(None) => {}
+        Ok(Some(_)) => return Err("Username Taken".to_owned()),
+        Err(e) => return Err(e.to_string()),
+    }
+    let display_name_taken = sqlx::query!(
+        "SELECT true FROM users WHERE display_name = ? COLLATE NOCASE;",
+        display_name
+    )
+    .fetch_optional(&mut *conn)
+    .await;
+    match display_name_taken {
+        Ok(None) => {}
+        Ok(Some(_)) => return Err("Display Name Taken".to_owned()),
+        Err(e) => return Err(e.to_string()),
+    }
+    drop(conn);
     let secret = Secret::generate_secret();
     let otp = get_otp(secret, &username).unwrap();
-    let qr_code = otp.get_qr_base64().unwrap();
     let secret = otp.get_secret_base32();
-    let mut conn = match state.db.acquire().await {
-        Ok(v) => v,
-        Err(e) => return err(e.to_string()),
-    };
-    match sqlx::query!(
-        "INSERT INTO users VALUES (?,?,?,1000,FALSE);",
-        otp.account_name,
-        data.display_name,
-        secret
+    let mut tx = db.begin().await.map_err(|e| e.to_string())?;
+    let inserted = sqlx::query!(
+        "INSERT INTO users (username, display_name, secret, otp_verified, role)
+         VALUES (?,?,?,FALSE,?);",
+        username,
+        display_name,
+        secret,
+        role
     )
-    .execute(&mut *conn)
-    .await
-    {
-        Ok(_) => render_html(|| {
-            view! {
-                <div>
-                    <Base64Image base64=qr_code alt="Qr Code".to_string()/>
-                    <p>OTP Secret:{secret}</p>
-                </div>
+    .execute(&mut *tx)
+    .await;
+    // an empty treasury shouldn't stop people from signing up, they're told instead
+    let mut grant_missing = false;
+    if inserted.is_ok() && starting_grant.is_positive() {
+        let grant = match to_minor_units(&mut tx, DEFAULT_CURRENCY, starting_grant).await {
+            Ok(amount) => {
+                record_transfer(
+                    &mut tx,
+                    TREASURY,
+                    &username,
+                    "Starting grant",
+                    DEFAULT_CURRENCY,
+                    amount,
+                )
+                .await
             }
-        }),
-        Err(_) => render_html(|| {
-            view! {
-                Error while inserting user into Database
-                <RegisterForm />
+            Err(e) => Err(e),
+        };
+        match grant {
+            Ok(_) => {}
+            Err(TransferError::InsufficientFunds) => {
+                println!("Treasury can't pay the starting grant for {username}");
+                grant_missing = true;
             }
-        }),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    let inserted = match inserted {
+        Ok(_) => tx.commit().await,
+        Err(e) => Err(e),
+    };
+    match inserted {
+        Ok(_) => Ok(NewAccount { otp, grant_missing }),
+        // Someone else registered the same name between the check and the insert
+        Err(sqlx::Error::Database(e))
+            if e.is_unique_violation() && e.message().contains("display_name") =>
+        {
+            Err("Display Name Taken".to_owned())
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            Err("Username Taken".to_owned())
+        }
+        Err(e) => Err(format!("Error while inserting user into Database: {e}")),
+    }
+}
+
+/// Names that could be mistaken for the server or its staff
+const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "system",
+    "server",
+    "root",
+    "treasury",
+    "bank",
+    "schmervices",
+];
+
+/// Usernames follow the minecraft rules: 3-16 chars of `a-z`, `0-9` and `_`.
+/// expects an already lowercased username
+fn validate_username(username: &str) -> Result<(), String> {
+    if !(3..=16).contains(&username.len()) {
+        return Err("Username has to be between 3 and 16 characters long".to_owned());
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        return Err("Username may only contain letters, numbers and '_'".to_owned());
+    }
+    if RESERVED_USERNAMES.contains(&username) {
+        return Err("Username is reserved".to_owned());
+    }
+    Ok(())
+}
+
+fn validate_display_name(display_name: &str) -> Result<(), String> {
+    let len = display_name.chars().count();
+    if !(1..=32).contains(&len) {
+        return Err("Display Name has to be between 1 and 32 characters long".to_owned());
+    }
+    if display_name.chars().any(char::is_control) {
+        return Err("Display Name Contains Forbidden Characters".to_owned());
     }
+    Ok(())
 }
 #[async_trait]
 impl<S> FromRequestParts<S> for App
@@ -253,8 +538,8 @@ async fn check_db_for_auth_token(token: &str, db: &App) -> Option<String> {
         .timestamp();
     let _ = sqlx::query!(
         "UPDATE auth_tokens SET expire_timestamp = ? WHERE token = ?;",
-        token,
-        new
+        new,
+        token
     )
     .execute(&mut *conn)
     .await;
@@ -313,3 +598,96 @@ where
         Ok(out)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::{events::Events, money::Money};
+
+    async fn app() -> App {
+        // a single connection, every new one would open a different in-memory database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (username, display_name, secret, otp_verified)
+             VALUES ('alice', 'Alice', '', TRUE);",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        App {
+            db: Arc::new(pool),
+            events: Events::default(),
+            starting_grant: Money::new(0, 0),
+            public_url: String::new(),
+        }
+    }
+
+    async fn tokens(app: &App, client: ClientType) -> Vec<String> {
+        sqlx::query_scalar(
+            "SELECT token FROM auth_tokens WHERE username = 'alice' AND client = ? ORDER BY rowid;",
+        )
+        .bind(client)
+        .fetch_all(&*app.db)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cap_evicts_the_oldest_token_per_client() {
+        let app = app().await;
+        let api = gen_token_and_store_in_db(&app, "alice", ClientType::Api, None)
+            .await
+            .unwrap();
+        let mut web = Vec::new();
+        for _ in 0..get_max_tokens_per_client() + 2 {
+            let token = gen_token_and_store_in_db(&app, "alice", ClientType::Web, None)
+                .await
+                .unwrap();
+            web.push(token);
+        }
+        let kept = web.split_off(2);
+        assert_eq!(tokens(&app, ClientType::Web).await, kept);
+        // the other kind of client keeps its login
+        assert_eq!(tokens(&app, ClientType::Api).await, [api]);
+
+        // replacing a token doesn't log out another one
+        let newer = gen_token_and_store_in_db(&app, "alice", ClientType::Web, Some(&kept[0]))
+            .await
+            .unwrap();
+        let mut expected = kept[1..].to_vec();
+        expected.push(newer);
+        assert_eq!(tokens(&app, ClientType::Web).await, expected);
+    }
+
+    #[tokio::test]
+    async fn using_a_token_extends_it() {
+        let app = app().await;
+        let token = gen_token_and_store_in_db(&app, "alice", ClientType::Web, None)
+            .await
+            .unwrap();
+        let soon = chrono::Utc::now().timestamp() + 60;
+        sqlx::query("UPDATE auth_tokens SET expire_timestamp = ?;")
+            .bind(soon)
+            .execute(&*app.db)
+            .await
+            .unwrap();
+        assert_eq!(
+            check_db_for_auth_token(&token, &app).await.as_deref(),
+            Some("alice")
+        );
+        let expires: i64 = sqlx::query_scalar("SELECT expire_timestamp FROM auth_tokens;")
+            .fetch_one(&*app.db)
+            .await
+            .unwrap();
+        assert!(expires > soon);
+    }
+}
@@ -0,0 +1,274 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::post,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::AuthUser,
+    db_utils::is_frozen,
+    ledger::{default_currency, to_minor_units},
+    money::Money,
+    util::ApiRequest,
+    App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/add_product", post(add_product))
+        .route("/remove_product/:sku", post(remove_product))
+        .route("/set_stock/:sku", post(set_stock))
+        .route("/list_products", post(list_products))
+}
+
+#[derive(Serialize, Debug)]
+pub struct Product {
+    pub sku: String,
+    pub title: String,
+    pub description: String,
+    pub price: Money,
+    pub currency: String,
+    pub stock: i64,
+}
+
+/// A product like it's stored, the price in the smallest unit of the currency
+pub(crate) struct StoredProduct {
+    pub title: String,
+    pub price: i64,
+    pub currency: String,
+    pub stock: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProductData {
+    sku: String,
+    title: String,
+    #[serde(default)]
+    description: String,
+    price: Money,
+    #[serde(default = "default_currency")]
+    currency: String,
+    stock: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct StockData {
+    stock: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListProducts {
+    seller: String,
+}
+
+fn is_valid_sku(sku: &str) -> bool {
+    (1..=32).contains(&sku.len())
+        && sku
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub(crate) async fn get_product(
+    conn: &mut SqliteConnection,
+    seller: &str,
+    sku: &str,
+) -> sqlx::Result<Option<StoredProduct>> {
+    sqlx::query_as!(
+        StoredProduct,
+        "SELECT title, price, currency, stock FROM products WHERE seller = ? AND sku = ?;",
+        seller,
+        sku
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Takes one of the product out of stock, false if there is none left
+pub(crate) async fn take_stock(
+    conn: &mut SqliteConnection,
+    seller: &str,
+    sku: &str,
+) -> sqlx::Result<bool> {
+    let r = sqlx::query!(
+        "UPDATE products SET stock = stock - 1 WHERE seller = ? AND sku = ? AND stock > 0;",
+        seller,
+        sku
+    )
+    .execute(conn)
+    .await?;
+    Ok(r.rows_affected() == 1)
+}
+
+/// Puts one of the product back, does nothing if the seller removed it since
+pub(crate) async fn restock(
+    conn: &mut SqliteConnection,
+    seller: &str,
+    sku: &str,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE products SET stock = stock + 1 WHERE seller = ? AND sku = ?;",
+        seller,
+        sku
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_products(conn: &mut SqliteConnection, seller: &str) -> sqlx::Result<Vec<Product>> {
+    let rows = sqlx::query!(
+        "SELECT sku, title, description, price, products.currency, stock, currencies.decimals
+         FROM products JOIN currencies ON currencies.code = products.currency
+         WHERE seller = ? ORDER BY sku;",
+        seller
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| Product {
+            sku: r.sku,
+            title: r.title,
+            description: r.description,
+            price: Money::new(r.price, r.decimals as u8),
+            currency: r.currency,
+            stock: r.stock,
+        })
+        .collect())
+}
+
+/// Adds a product to the sellers catalog, or replaces the one with the same sku
+async fn add_product(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<ProductData>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let title = data.title.trim();
+    if !is_valid_sku(&data.sku)
+        || title.is_empty()
+        || title.len() > 64
+        || data.description.len() > 256
+        || !data.price.is_positive()
+        || data.stock < 0
+    {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    match is_frozen(&state, &user).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let price = to_minor_units(&mut conn, &data.currency, data.price).await?;
+    sqlx::query!(
+        "INSERT INTO products (seller, sku, title, description, price, currency, stock)
+         VALUES (?,?,?,?,?,?,?)
+         ON CONFLICT (seller, sku) DO UPDATE SET
+            title = excluded.title,
+            description = excluded.description,
+            price = excluded.price,
+            currency = excluded.currency,
+            stock = excluded.stock;",
+        user,
+        data.sku,
+        title,
+        data.description,
+        price,
+        data.currency,
+        data.stock
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
+
+async fn remove_product(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(sku): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let r = sqlx::query!(
+        "DELETE FROM products WHERE seller = ? AND sku = ?;",
+        user,
+        sku
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn set_stock(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(sku): Path<String>,
+    ApiRequest(data): ApiRequest<StockData>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    if data.stock < 0 {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    let r = sqlx::query!(
+        "UPDATE products SET stock = ? WHERE seller = ? AND sku = ?;",
+        data.stock,
+        user,
+        sku
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    Ok(StatusCode::OK)
+}
+
+/// Everything a seller offers, no login needed
+async fn list_products(
+    State(state): State<App>,
+    ApiRequest(data): ApiRequest<ListProducts>,
+) -> Result<Json<Vec<Product>>, StatusCode> {
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let products = get_products(&mut conn, &data.seller)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(products))
+}
@@ -0,0 +1,266 @@
+use std::path::Path;
+
+use eyre::{bail, eyre, WrapErr};
+use sqlx::SqliteConnection;
+
+use crate::{
+    admin::write_audit_log,
+    api::fetch_transactions,
+    auth::create_account,
+    ledger::{
+        change_money_supply, record_transfer, to_minor_units, TransferError, DEFAULT_CURRENCY,
+        TREASURY,
+    },
+    money::Money,
+    util::format_timestamp,
+    App,
+};
+
+/// Shows up as the admin in the audit log for changes made from the shell
+const CLI_ADMIN: &str = "cli";
+
+/// Runs a subcommand instead of the server, `args` starts with its name
+pub async fn run_command(state: &App, args: &[String]) -> eyre::Result<()> {
+    let (command, args) = args.split_first().ok_or_else(|| eyre!("no command"))?;
+    match command.as_str() {
+        "migrate" => {
+            // run already applied them, even with --no-migrate
+            no_more(args)?;
+            println!("Database is up to date");
+        }
+        "create-user" => create_user(state, args).await?,
+        "mint" => mint(state, args).await?,
+        "grant" => grant(state, args).await?,
+        "list-transactions" => list_transactions(state, args).await?,
+        "backup" => backup(state, args).await?,
+        _ => bail!("unknown command {command}, see --help"),
+    }
+    Ok(())
+}
+
+/// The arguments of a subcommand
+struct SplitArgs<'a> {
+    positional: Vec<&'a str>,
+    /// flag and value, switches have an empty one
+    flags: Vec<(&'a str, &'a str)>,
+}
+
+/// Splits the arguments into positional ones and `--flag value` pairs,
+/// only the flags in `known` are allowed and `switches` don't take a value
+fn split_args<'a>(
+    args: &'a [String],
+    known: &[&str],
+    switches: &[&str],
+) -> eyre::Result<SplitArgs<'a>> {
+    let mut positional = Vec::new();
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg.as_str());
+            continue;
+        }
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if switches.contains(&flag) {
+            flags.push((flag, ""));
+        } else if known.contains(&flag) {
+            let value = match inline {
+                Some(value) => value,
+                None => args.next().ok_or_else(|| eyre!("{flag} needs a value"))?,
+            };
+            flags.push((flag, value));
+        } else {
+            bail!("unknown option {flag}, see --help");
+        }
+    }
+    Ok(SplitArgs { positional, flags })
+}
+
+fn no_more(args: &[String]) -> eyre::Result<()> {
+    match args.first() {
+        Some(arg) => bail!("unexpected argument {arg}, see --help"),
+        None => Ok(()),
+    }
+}
+
+async fn create_user(state: &App, args: &[String]) -> eyre::Result<()> {
+    let SplitArgs { positional, flags } = split_args(args, &[], &["--admin"])?;
+    let [username, display_name] = positional[..] else {
+        bail!("usage: create-user <NAME> <DISPLAY> [--admin]");
+    };
+    let role = if flags.is_empty() { "user" } else { "admin" };
+    let account = create_account(
+        &state.db,
+        username,
+        display_name,
+        role,
+        state.starting_grant,
+    )
+    .await
+    .map_err(|e| eyre!(e))?;
+    let otp = account.otp;
+    println!("Created {} as {role}", otp.account_name);
+    println!("OTP Secret: {}", otp.get_secret_base32());
+    println!("{}", otp.get_url());
+    if account.grant_missing {
+        println!("The treasury can't pay the starting grant, mint and grant it by hand");
+    }
+    Ok(())
+}
+
+/// The amount, `--currency` and `--reason` of mint and grant
+struct Payment<'a> {
+    amount: Money,
+    currency: &'a str,
+    reason: &'a str,
+}
+
+fn payment<'a>(
+    amount: &str,
+    flags: Vec<(&'a str, &'a str)>,
+    reason: &'a str,
+) -> eyre::Result<Payment<'a>> {
+    let amount: Money = amount.parse().map_err(|e| eyre!("amount: {e}"))?;
+    if !amount.is_positive() {
+        bail!("the amount has to be more than 0");
+    }
+    let mut payment = Payment {
+        amount,
+        currency: DEFAULT_CURRENCY,
+        reason,
+    };
+    for (flag, value) in flags {
+        match flag {
+            "--currency" => payment.currency = value,
+            _ => payment.reason = value,
+        }
+    }
+    Ok(payment)
+}
+
+async fn minor_units(conn: &mut SqliteConnection, payment: &Payment<'_>) -> eyre::Result<i64> {
+    let currency = payment.currency;
+    match to_minor_units(conn, currency, payment.amount).await {
+        Ok(minor) => Ok(minor),
+        Err(TransferError::UnknownCurrency) => bail!("unknown currency {currency}"),
+        Err(TransferError::InvalidAmount(_)) => bail!("invalid amount for {currency}"),
+        Err(e) => bail!(e.to_string()),
+    }
+}
+
+/// Creates money in the treasury, like the admin panel does
+async fn mint(state: &App, args: &[String]) -> eyre::Result<()> {
+    let SplitArgs { positional, flags } = split_args(args, &["--currency", "--reason"], &[])?;
+    let [amount] = positional[..] else {
+        bail!("usage: mint <AMOUNT> [--currency <CODE>] [--reason <TEXT>]");
+    };
+    let payment = payment(amount, flags, "Minted from the command line")?;
+    let mut tx = state.db.begin().await?;
+    let minor = minor_units(&mut tx, &payment).await?;
+    let Payment {
+        amount,
+        currency,
+        reason,
+    } = payment;
+    change_money_supply(&mut tx, CLI_ADMIN, currency, minor, reason)
+        .await
+        .map_err(|e| eyre!(e.to_string()))?;
+    let action = format!("mint {amount} {currency}");
+    write_audit_log(&mut tx, CLI_ADMIN, &action, TREASURY, reason).await?;
+    tx.commit().await?;
+    println!("Minted {amount} {currency} into the treasury");
+    Ok(())
+}
+
+async fn grant(state: &App, args: &[String]) -> eyre::Result<()> {
+    let SplitArgs { positional, flags } = split_args(args, &["--currency", "--reason"], &[])?;
+    let [username, amount] = positional[..] else {
+        bail!("usage: grant <NAME> <AMOUNT> [--currency <CODE>] [--reason <TEXT>]");
+    };
+    let payment = payment(amount, flags, "Granted from the command line")?;
+    let mut tx = state.db.begin().await?;
+    let minor = minor_units(&mut tx, &payment).await?;
+    let Payment {
+        amount,
+        currency,
+        reason,
+    } = payment;
+    // same as an admin adjusting the balance, so the money supply still adds up
+    match record_transfer(
+        &mut tx,
+        TREASURY,
+        username,
+        "Balance adjustment",
+        currency,
+        minor,
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(TransferError::InsufficientFunds) => {
+            bail!("the treasury doesn't have enough {currency}, mint some first")
+        }
+        Err(TransferError::UnknownAccount) => bail!("user {username} not found"),
+        Err(e) => bail!(e.to_string()),
+    }
+    let action = format!("adjust_balance +{amount} {currency}");
+    write_audit_log(&mut tx, CLI_ADMIN, &action, username, reason).await?;
+    tx.commit().await?;
+    state.events.changed([username]);
+    println!("Granted {amount} {currency} to {username}");
+    Ok(())
+}
+
+async fn list_transactions(state: &App, args: &[String]) -> eyre::Result<()> {
+    let SplitArgs { positional, flags } = split_args(args, &["--user", "--limit"], &[])?;
+    if let Some(arg) = positional.first() {
+        bail!("unexpected argument {arg}, see --help");
+    }
+    let mut user = None;
+    let mut limit = 20;
+    for (flag, value) in flags {
+        match flag {
+            "--user" => user = Some(value),
+            _ => {
+                limit = value
+                    .parse()
+                    .map_err(|_| eyre!("--limit needs a whole number, got {value}"))?
+            }
+        }
+    }
+    let mut conn = state.db.acquire().await?;
+    for t in fetch_transactions(&mut conn, user, limit).await? {
+        println!(
+            "{}  {}  {} -> {}  {} {}  {}  {}",
+            format_timestamp(t.timestamp),
+            t.id,
+            t.buyer,
+            t.seller,
+            t.amount,
+            t.currency,
+            t.status,
+            t.name
+        );
+    }
+    Ok(())
+}
+
+async fn backup(state: &App, args: &[String]) -> eyre::Result<()> {
+    let [path] = args else {
+        bail!("usage: backup <FILE>");
+    };
+    // VACUUM INTO fails on existing files anyway, this says why
+    if Path::new(path).exists() {
+        bail!("{path} already exists, won't overwrite it");
+    }
+    sqlx::query("VACUUM INTO ?;")
+        .bind(path)
+        .execute(&*state.db)
+        .await
+        .wrap_err_with(|| format!("couldn't back up to {path}"))?;
+    println!("Backed up to {path}");
+    Ok(())
+}
@@ -0,0 +1,445 @@
+use std::{
+    env, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use eyre::{bail, eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+
+/// Read when no `--config` is given, it's fine if it doesn't exist
+pub const DEFAULT_CONFIG_FILE: &str = "schmervices.toml";
+
+const MAX_TTL_MINUTES: i64 = 60 * 24 * 3650;
+
+pub const USAGE: &str = "\
+Usage: schmervices [OPTIONS] [COMMAND]
+
+Without a command the server is started.
+
+Commands:
+  migrate                              apply the migrations and exit
+  create-user <NAME> <DISPLAY> [--admin]
+                                       add an account, prints its passcode secret
+  mint <AMOUNT> [--currency <CODE>] [--reason <TEXT>]
+                                       create money in the treasury, it starts out empty
+  grant <NAME> <AMOUNT> [--currency <CODE>] [--reason <TEXT>]
+                                       pay someone from the treasury, mint first
+  list-transactions [--user <NAME>] [--limit <N>]
+                                       newest transactions first [default limit: 20]
+  backup <FILE>                        copy the database to a new file
+
+Options, these go before the command:
+  --config <FILE>                      config file [default: schmervices.toml if it exists]
+  --print-config                       print the config that would be used and exit
+  --no-migrate                         don't apply the migrations on startup
+  --bind <ADDR>                        address to listen on [default: 0.0.0.0:3000]
+  --public-url <URL>                   where players reach the server [default: http://localhost:3000]
+  --database-url <URL>                 sqlite database [default: $DATABASE_URL]
+  --static-dir <DIR>                   files served under /lua [default: lua]
+  --starting-grant <AMOUNT>            money new accounts get [default: 1000]
+  --max-tokens-per-client <N>          logins per user and kind of client [default: 5]
+  --transaction-ttl-minutes <N>        time to answer a transaction [default: 10]
+  --hold-ttl-hours <N>                 time money can stay on hold [default: 48]
+  --auth-token-days <N>                time a login lasts unused [default: 7]
+  --subscription-retry-minutes <N>     wait before retrying a failed charge [default: 60]
+  --subscription-grace-hours <N>       time before an unpaid subscription lapses [default: 72]
+  -h, --help                           print this
+";
+
+/// Everything about the server that can be set without rebuilding it.
+/// Flags win over the config file, which wins over the defaults.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: String,
+    /// where players reach the server, payment links point there
+    pub public_url: String,
+    /// `DATABASE_URL` is used when this isn't set anywhere
+    pub database_url: Option<String>,
+    /// apply the migrations in `migrations/` on startup
+    pub migrate: bool,
+    pub static_dir: PathBuf,
+    pub economy: Economy,
+    pub ttl: Ttl,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Economy {
+    /// money new accounts get from the treasury
+    pub starting_grant: Money,
+    pub max_tokens_per_client: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ttl {
+    pub transaction_minutes: i64,
+    pub hold_hours: i64,
+    pub auth_token_days: i64,
+    pub subscription_retry_minutes: i64,
+    pub subscription_grace_hours: i64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: "0.0.0.0:3000".to_owned(),
+            public_url: "http://localhost:3000".to_owned(),
+            database_url: None,
+            migrate: true,
+            static_dir: PathBuf::from("lua"),
+            economy: Economy::default(),
+            ttl: Ttl::default(),
+        }
+    }
+}
+
+impl Default for Economy {
+    fn default() -> Self {
+        Economy {
+            starting_grant: Money::new(1000, 0),
+            max_tokens_per_client: 5,
+        }
+    }
+}
+
+impl Default for Ttl {
+    fn default() -> Self {
+        Ttl {
+            transaction_minutes: 10,
+            hold_hours: 48,
+            auth_token_days: 7,
+            subscription_retry_minutes: 60,
+            subscription_grace_hours: 72,
+        }
+    }
+}
+
+/// What the command line asked for
+#[derive(Debug, Default)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub print_config: bool,
+    pub help: bool,
+    /// the subcommand and its arguments, empty to start the server
+    pub command: Vec<String>,
+    /// flag and value of everything that overrides the config
+    overrides: Vec<(String, String)>,
+}
+
+/// Takes `--flag value` and `--flag=value`, everything from the first
+/// argument that isn't a flag on belongs to the subcommand
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> eyre::Result<Args> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_owned(), Some(value.to_owned())),
+            None => (arg, None),
+        };
+        match flag.as_str() {
+            "-h" | "--help" => parsed.help = true,
+            "--print-config" => parsed.print_config = true,
+            "--no-migrate" => parsed
+                .overrides
+                .push(("--migrate".to_owned(), "false".to_owned())),
+            _ if flag.starts_with("--") => {
+                let value = match inline {
+                    Some(value) => value,
+                    None => args.next().ok_or_else(|| eyre!("{flag} needs a value"))?,
+                };
+                if flag == "--config" {
+                    parsed.config = Some(PathBuf::from(value));
+                } else {
+                    parsed.overrides.push((flag, value));
+                }
+            }
+            _ => {
+                parsed.command.push(arg_of(flag, inline));
+                parsed.command.extend(args);
+                break;
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+/// Puts back together what was split at the `=`
+fn arg_of(flag: String, inline: Option<String>) -> String {
+    match inline {
+        Some(value) => format!("{flag}={value}"),
+        None => flag,
+    }
+}
+
+fn number(flag: &str, value: &str) -> eyre::Result<i64> {
+    value
+        .parse()
+        .map_err(|_| eyre!("{flag} needs a whole number, got {value}"))
+}
+
+impl Config {
+    fn read(path: &Path) -> eyre::Result<Config> {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("couldn't read {}", path.display()))?;
+        toml::from_str(&text).wrap_err_with(|| format!("invalid config in {}", path.display()))
+    }
+
+    fn apply(&mut self, flag: &str, value: String) -> eyre::Result<()> {
+        match flag {
+            "--bind" => self.bind = value,
+            "--public-url" => self.public_url = value,
+            "--database-url" => self.database_url = Some(value),
+            "--migrate" => {
+                self.migrate = value
+                    .parse()
+                    .map_err(|_| eyre!("--migrate needs true or false, got {value}"))?
+            }
+            "--static-dir" => self.static_dir = PathBuf::from(value),
+            "--starting-grant" => {
+                self.economy.starting_grant =
+                    value.parse().map_err(|e| eyre!("--starting-grant: {e}"))?
+            }
+            "--max-tokens-per-client" => self.economy.max_tokens_per_client = number(flag, &value)?,
+            "--transaction-ttl-minutes" => self.ttl.transaction_minutes = number(flag, &value)?,
+            "--hold-ttl-hours" => self.ttl.hold_hours = number(flag, &value)?,
+            "--auth-token-days" => self.ttl.auth_token_days = number(flag, &value)?,
+            "--subscription-retry-minutes" => {
+                self.ttl.subscription_retry_minutes = number(flag, &value)?
+            }
+            "--subscription-grace-hours" => {
+                self.ttl.subscription_grace_hours = number(flag, &value)?
+            }
+            _ => bail!("unknown option {flag}, see --help"),
+        }
+        Ok(())
+    }
+
+    /// The config file, then the flags on top. Not validated yet.
+    pub fn load(args: &Args) -> eyre::Result<Config> {
+        let mut config = match &args.config {
+            Some(path) => Config::read(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::read(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+        for (flag, value) in &args.overrides {
+            config.apply(flag, value.clone())?;
+        }
+        if config.database_url.is_none() {
+            config.database_url = env::var("DATABASE_URL").ok();
+        }
+        Ok(config)
+    }
+
+    /// Lists everything wrong at once instead of failing on the first.
+    /// `bind` and `static_dir` only matter when `serving`, commands can run from anywhere.
+    pub fn validate(&self, serving: bool) -> eyre::Result<()> {
+        let mut problems = Vec::new();
+        if serving && self.bind.parse::<SocketAddr>().is_err() {
+            problems.push(format!(
+                "bind: {} isn't an address like 0.0.0.0:3000",
+                self.bind
+            ));
+        }
+        if !self.public_url.starts_with("http://") && !self.public_url.starts_with("https://") {
+            problems.push(format!(
+                "public_url: {} isn't an http:// or https:// url",
+                self.public_url
+            ));
+        }
+        match &self.database_url {
+            Some(url) if url.starts_with("sqlite:") => {}
+            Some(url) => problems.push(format!("database_url: {url} isn't a sqlite: url")),
+            None => problems.push("database_url: not set and DATABASE_URL is empty".to_owned()),
+        }
+        if serving && !self.static_dir.is_dir() {
+            problems.push(format!(
+                "static_dir: {} isn't a directory",
+                self.static_dir.display()
+            ));
+        }
+        if self.economy.starting_grant.minor() < 0 {
+            problems.push("economy.starting_grant: can't be negative".to_owned());
+        }
+        if self.economy.max_tokens_per_client < 1 {
+            problems.push("economy.max_tokens_per_client: has to be at least 1".to_owned());
+        }
+        // at most 10 years, anything bigger overflows chrono's Duration
+        let ttls = [
+            (
+                "transaction_minutes",
+                self.ttl.transaction_minutes,
+                MAX_TTL_MINUTES,
+            ),
+            ("hold_hours", self.ttl.hold_hours, MAX_TTL_MINUTES / 60),
+            (
+                "auth_token_days",
+                self.ttl.auth_token_days,
+                MAX_TTL_MINUTES / (60 * 24),
+            ),
+            (
+                "subscription_retry_minutes",
+                self.ttl.subscription_retry_minutes,
+                MAX_TTL_MINUTES,
+            ),
+            (
+                "subscription_grace_hours",
+                self.ttl.subscription_grace_hours,
+                MAX_TTL_MINUTES / 60,
+            ),
+        ];
+        for (name, value, max) in ttls {
+            if !(1..=max).contains(&value) {
+                problems.push(format!("ttl.{name}: has to be between 1 and {max}"));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            bail!("invalid config:\n  {}", problems.join("\n  "))
+        }
+    }
+
+    pub fn database_url(&self) -> &str {
+        self.database_url.as_deref().unwrap_or_default()
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Makes the config the one `get` returns, only the first call counts
+pub fn set(config: Config) {
+    _ = CONFIG.set(config);
+}
+
+/// The config the server was started with, the defaults if it wasn't set
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> eyre::Result<Args> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    fn valid() -> Config {
+        Config {
+            database_url: Some("sqlite:money.db".to_owned()),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn flags_with_and_without_equals() {
+        let parsed = args(&[
+            "--config",
+            "a.toml",
+            "--bind=127.0.0.1:80",
+            "--print-config",
+        ])
+        .unwrap();
+        assert_eq!(parsed.config, Some(PathBuf::from("a.toml")));
+        assert!(parsed.print_config);
+        assert!(!parsed.help);
+        assert_eq!(
+            parsed.overrides,
+            [("--bind".to_owned(), "127.0.0.1:80".to_owned())]
+        );
+        assert!(args(&["-h"]).unwrap().help);
+    }
+
+    #[test]
+    fn bad_args() {
+        assert!(args(&["--bind"]).is_err());
+        let mut config = Config::default();
+        assert!(config.apply("--nope", "1".to_owned()).is_err());
+        assert!(config.apply("--hold-ttl-hours", "soon".to_owned()).is_err());
+        assert!(config.apply("--starting-grant", "lots".to_owned()).is_err());
+    }
+
+    #[test]
+    fn commands_keep_their_flags() {
+        let parsed = args(&["--no-migrate", "grant", "bob", "5", "--reason=gift"]).unwrap();
+        assert_eq!(parsed.command, ["grant", "bob", "5", "--reason=gift"]);
+        assert_eq!(
+            parsed.overrides,
+            [("--migrate".to_owned(), "false".to_owned())]
+        );
+    }
+
+    #[test]
+    fn flags_override_the_config() {
+        let mut config = Config::default();
+        config.apply("--hold-ttl-hours", "12".to_owned()).unwrap();
+        config.apply("--starting-grant", "2.5".to_owned()).unwrap();
+        config
+            .apply("--public-url", "https://example.com".to_owned())
+            .unwrap();
+        assert_eq!(config.ttl.hold_hours, 12);
+        assert_eq!(config.economy.starting_grant, Money::new(25, 1));
+        assert_eq!(config.public_url, "https://example.com");
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        valid().validate(true).unwrap();
+    }
+
+    #[test]
+    fn validate_lists_every_problem() {
+        let mut config = valid();
+        config.bind = "localhost".to_owned();
+        config.public_url = "example.com".to_owned();
+        config.database_url = Some("postgres://db".to_owned());
+        config.economy.max_tokens_per_client = 0;
+        let err = config.validate(true).unwrap_err().to_string();
+        for field in [
+            "bind",
+            "public_url",
+            "database_url",
+            "max_tokens_per_client",
+        ] {
+            assert!(err.contains(field), "{field} missing in {err}");
+        }
+    }
+
+    #[test]
+    fn commands_dont_need_a_server() {
+        let mut config = valid();
+        config.bind = "localhost".to_owned();
+        config.static_dir = PathBuf::from("no such dir");
+        assert!(config.validate(true).is_err());
+        config.validate(false).unwrap();
+    }
+
+    #[test]
+    fn ttls_are_bounded() {
+        let mut config = valid();
+        config.ttl.transaction_minutes = 0;
+        config.ttl.auth_token_days = i64::MAX;
+        let err = config.validate(true).unwrap_err().to_string();
+        assert!(err.contains("ttl.transaction_minutes"));
+        assert!(err.contains("ttl.auth_token_days"));
+        assert!(!err.contains("ttl.hold_hours"));
+
+        let mut config = valid();
+        config.ttl.transaction_minutes = MAX_TTL_MINUTES;
+        config.ttl.hold_hours = MAX_TTL_MINUTES / 60;
+        config.ttl.auth_token_days = MAX_TTL_MINUTES / (60 * 24);
+        config.validate(true).unwrap();
+        // the biggest allowed still fit in a Duration and a date
+        let now = chrono::Utc::now();
+        let days = chrono::Duration::days(config.ttl.auth_token_days);
+        assert!(now.checked_add_signed(days).is_some());
+    }
+}
@@ -0,0 +1,239 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{
+        header::{CONTENT_TYPE, SET_COOKIE},
+        HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
+use leptos::*;
+
+use crate::util::{get_random_string, get_requested_type, RequestTypeEnum};
+
+/// htmx sends the token in this header, see the `hx-headers` of the layout
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+/// Plain forms send it as this field
+pub const CSRF_FIELD: &str = "csrf";
+/// The browser keeps the token in this cookie, from the first page it opens on
+const CSRF_COOKIE: &str = "Money-Csrf";
+/// Forms are small, anything bigger than this isn't one of ours
+const FORM_LIMIT: usize = 64 * 1024;
+
+tokio::task_local! {
+    /// The token of the request being handled, for the pages it renders
+    static TOKEN: String;
+}
+
+/// The token pages have to send back with everything that changes something.
+/// It's random and only in a cookie other sites can't read, so they can't send it along,
+/// not even to the login and register forms before there is a login.
+pub fn csrf_token() -> Option<String> {
+    TOKEN.try_with(|token| token.clone()).ok()
+}
+
+/// Hidden field with the token for forms that might be sent without htmx
+#[component]
+pub fn csrf_field() -> impl IntoView {
+    csrf_token().map(|token| view! { <input type="hidden" name=CSRF_FIELD value=token/> })
+}
+
+/// Hands out the token cookie to browsers that don't have one yet
+async fn run(token: Option<String>, request: Request, next: Next) -> Response {
+    let Some(token) = token else {
+        let token = get_random_string(32);
+        let mut cookie = Cookie::new(CSRF_COOKIE, token.clone());
+        cookie.set_path("/");
+        cookie.set_http_only(Some(true));
+        cookie.set_same_site(Some(SameSite::Lax));
+        let mut response = TOKEN.scope(token, next.run(request)).await;
+        if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+            response.headers_mut().append(SET_COOKIE, value);
+        }
+        return response;
+    };
+    TOKEN.scope(token, next.run(request)).await
+}
+
+/// Turns away requests from browsers that change something without the token of their cookie.
+/// Requests logged in by the `Money-Auth-Key` header can't come from other sites and pass.
+pub async fn check_csrf(request: Request, next: Next) -> Result<Response, StatusCode> {
+    let jar = CookieJar::from_headers(request.headers());
+    let token = jar
+        .get(CSRF_COOKIE)
+        .map(|c| c.value().to_owned())
+        .filter(|t| !t.is_empty());
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let by_cookie = matches!(
+        get_requested_type(request.headers()),
+        Some(RequestTypeEnum::Html)
+    );
+    if safe || !by_cookie {
+        return Ok(run(token, request, next).await);
+    }
+    let Some(expected) = token else {
+        Err(StatusCode::FORBIDDEN)?
+    };
+    let header = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|h| h.to_str().ok());
+    if header == Some(expected.as_str()) {
+        return Ok(run(Some(expected), request, next).await);
+    }
+    let is_form = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| h.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        Err(StatusCode::FORBIDDEN)?;
+    }
+    // the form is read here and handed on as it was for ApiRequest
+    let (parts, body) = request.into_parts();
+    let bytes = to_bytes(body, FORM_LIMIT)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let fields: Vec<(String, String)> =
+        serde_urlencoded::from_bytes(&bytes).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !fields
+        .iter()
+        .any(|(name, value)| name == CSRF_FIELD && *value == expected)
+    {
+        Err(StatusCode::FORBIDDEN)?;
+    }
+    let request = Request::from_parts(parts, Body::from(bytes));
+    Ok(run(Some(expected), request, next).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::header::{ACCEPT, COOKIE},
+        middleware,
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app() -> Router {
+        let page = || async { csrf_token().unwrap_or_default() };
+        Router::new()
+            .route("/login", post(|| async { "ok" }).get(page))
+            .route("/api/send_money", post(|| async { "ok" }))
+            .layer(middleware::from_fn(check_csrf))
+    }
+
+    /// A form post from a page, with the cookie and header it was given
+    fn form(path: &str, cookie: Option<&str>, header: Option<&str>, body: &str) -> Request {
+        let mut request = Request::post(path)
+            .header(ACCEPT, "text/html")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(token) = cookie {
+            request = request.header(COOKIE, format!("{CSRF_COOKIE}={token}"));
+        }
+        if let Some(token) = header {
+            request = request.header(CSRF_HEADER, token);
+        }
+        request.body(Body::from(body.to_owned())).unwrap()
+    }
+
+    async fn status(request: Request) -> StatusCode {
+        app().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn pages_hand_out_a_token() {
+        let request = Request::get("/login")
+            .header(ACCEPT, "text/html")
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        let cookie = response.headers()[SET_COOKIE].to_str().unwrap().to_owned();
+        let body = to_bytes(response.into_body(), FORM_LIMIT).await.unwrap();
+        let token = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(token.len(), 32);
+        assert!(cookie.starts_with(&format!("{CSRF_COOKIE}={token};")));
+
+        // and keep the one the browser already has
+        let request = Request::get("/login")
+            .header(ACCEPT, "text/html")
+            .header(COOKIE, format!("{CSRF_COOKIE}=abc"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert!(!response.headers().contains_key(SET_COOKIE));
+        let body = to_bytes(response.into_body(), FORM_LIMIT).await.unwrap();
+        assert_eq!(&body[..], b"abc");
+    }
+
+    #[tokio::test]
+    async fn missing_token() {
+        let body = "username=a&otp=1";
+        assert_eq!(
+            status(form("/api/send_money", Some("abc"), None, body)).await,
+            StatusCode::FORBIDDEN
+        );
+        // without the cookie there is nothing to compare to
+        assert_eq!(
+            status(form("/api/send_money", None, Some("abc"), body)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(form("/api/send_money", Some(""), Some(""), body)).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn wrong_token() {
+        let request = form("/api/send_money", Some("abc"), Some("abd"), "to=a");
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+        let request = form("/api/send_money", Some("abc"), None, "to=a&csrf=abd");
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn matching_token() {
+        let request = form("/api/send_money", Some("abc"), Some("abc"), "to=a");
+        assert_eq!(status(request).await, StatusCode::OK);
+        let request = form("/api/send_money", Some("abc"), None, "to=a&csrf=abc");
+        assert_eq!(status(request).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn login_is_not_exempt() {
+        // another site could log the browser into its own account otherwise
+        let body = "username=a&otp=1";
+        assert_eq!(
+            status(form("/login", None, None, body)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(form("/login", Some("abc"), None, body)).await,
+            StatusCode::FORBIDDEN
+        );
+        let request = form("/login", Some("abc"), None, "username=a&otp=1&csrf=abc");
+        assert_eq!(status(request).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn header_clients_pass() {
+        let request = Request::post("/api/send_money")
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        assert_eq!(status(request).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,362 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Redirect, Response,
+    },
+};
+use axum_extra::extract::CookieJar;
+use futures_util::{stream, Stream, StreamExt};
+use leptos::{ssr::render_to_string as render, *};
+use sqlx::SqliteConnection;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    api::{fetch_pending, fetch_transactions, TransactionData},
+    auth::AuthUser,
+    csrf::CsrfField,
+    events::Event,
+    layout::{render_page, take_flash, Layout},
+    ledger::{get_balances, Balance, DEFAULT_CURRENCY},
+    util::format_timestamp,
+    App,
+};
+
+/// Everything the dashboard of a logged in user shows
+pub struct DashboardData {
+    pub username: String,
+    pub display_name: String,
+    pub balances: Vec<Balance>,
+    pub pending: Vec<TransactionData>,
+    pub history: Vec<TransactionData>,
+    pub currencies: Vec<String>,
+}
+
+pub async fn load_dashboard(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> sqlx::Result<DashboardData> {
+    let display_name = sqlx::query!(
+        "SELECT display_name FROM users WHERE username = ?;",
+        username
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .display_name;
+    let balances = get_balances(conn, username).await?;
+    let pending = fetch_pending(conn, username).await?;
+    let history = fetch_transactions(conn, Some(username), 20).await?;
+    let currencies = sqlx::query!("SELECT code FROM currencies ORDER BY code;")
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| r.code)
+        .collect();
+    Ok(DashboardData {
+        username: username.to_owned(),
+        display_name,
+        balances,
+        pending,
+        history,
+        currencies,
+    })
+}
+
+#[component]
+fn balances(balances: Vec<Balance>) -> impl IntoView {
+    let empty = balances.is_empty();
+    let balances = balances
+        .into_iter()
+        .map(|b| view! { <li>{b.amount.to_string()}" "{b.currency}</li> })
+        .collect_view();
+    view! {
+        <h2>Balance</h2>
+        <ul>{balances}</ul>
+        {empty.then_some(view! { <p>"Nothing yet"</p> })}
+    }
+}
+
+/// Requests waiting for the user, the buttons get replaced by how it went
+#[component]
+fn pending_approvals(pending: Vec<TransactionData>) -> impl IntoView {
+    let empty = pending.is_empty();
+    let rows = pending
+        .into_iter()
+        .map(|t| {
+            view! {
+                <tr>
+                    <td>{format_timestamp(t.timestamp)}</td>
+                    <td>{t.name}</td>
+                    <td>{t.seller}</td>
+                    <td>{t.amount.to_string()}" "{t.currency}</td>
+                    <td>
+                        <button
+                            hx-post=format!("/api/accept_transaction/{}", t.id)
+                            hx-target="closest td"
+                            class="button"
+                        >
+                            Accept
+                        </button>
+                        <button
+                            hx-post=format!("/api/reject_transaction/{}", t.id)
+                            hx-target="closest td"
+                            class="button"
+                        >
+                            Reject
+                        </button>
+                    </td>
+                </tr>
+            }
+        })
+        .collect_view();
+    view! {
+        <h2>Waiting for you</h2>
+        <table>{rows}</table>
+        {empty.then_some(view! { <p>"Nothing to approve"</p> })}
+    }
+}
+
+#[component]
+fn history(username: String, transactions: Vec<TransactionData>) -> impl IntoView {
+    let rows = transactions
+        .into_iter()
+        .map(|t| {
+            let (sign, other) = if t.buyer == username {
+                ("-", t.seller)
+            } else {
+                ("+", t.buyer)
+            };
+            view! {
+                <tr>
+                    <td>{format_timestamp(t.timestamp)}</td>
+                    <td>{t.name}</td>
+                    <td>{other}</td>
+                    <td class="text-right">{sign}{t.amount.to_string()}" "{t.currency}</td>
+                    <td>{t.status.as_str()}</td>
+                    <td><a href=format!("/api/invoice/{}", t.id)>Invoice</a></td>
+                </tr>
+            }
+        })
+        .collect_view();
+    view! {
+        <h2>History</h2>
+        <table>{rows}</table>
+    }
+}
+
+#[component]
+fn transfer_form(currencies: Vec<String>) -> impl IntoView {
+    let currencies = currencies
+        .into_iter()
+        .map(|c| {
+            let selected = c == DEFAULT_CURRENCY;
+            view! { <option value=c.clone() selected=selected>{c}</option> }
+        })
+        .collect_view();
+    view! {
+        <h2>Send Money</h2>
+        <form hx-post="/api/send_money" hx-target="#transfer-result">
+            <CsrfField/>
+            <input type="text" name="to" placeholder="Username" required/>
+            <input type="number" name="amount" placeholder="Amount" min="0" step="any" required/>
+            <select name="currency">{currencies}</select>
+            <input type="text" name="memo" placeholder="What for"/>
+            <button class="button">Send</button>
+            <span id="transfer-result"></span>
+        </form>
+    }
+}
+
+/// The parts with `sse-swap` get replaced by the matching event of /events
+#[component]
+pub fn dashboard(data: DashboardData) -> impl IntoView {
+    view! {
+        <h1>"Hello "{data.display_name}</h1>
+        <div hx-ext="sse" sse-connect="/events">
+            <div sse-swap="balance">
+                <Balances balances=data.balances/>
+            </div>
+            <div sse-swap="pending">
+                <PendingApprovals pending=data.pending/>
+            </div>
+            <TransferForm currencies=data.currencies/>
+            <div sse-swap="history">
+                <History username=data.username transactions=data.history/>
+            </div>
+        </div>
+    }
+}
+
+/// Pages that only make sense logged in send everyone else to the login page
+fn login_first() -> Response {
+    Redirect::to("/login").into_response()
+}
+
+pub async fn dashboard_page(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    jar: CookieJar,
+) -> Result<Response, StatusCode> {
+    let Some((user, _)) = user else {
+        return Ok(login_first());
+    };
+    let data = load_dashboard(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        &user,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (jar, flash) = take_flash(jar);
+    let html = render_page(move || {
+        view! {
+            <Layout title="Dashboard" user=Some(user) flash=flash>
+                <Dashboard data=data/>
+            </Layout>
+        }
+    });
+    Ok((jar, html).into_response())
+}
+
+pub async fn history_page(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Response, StatusCode> {
+    let Some((user, _)) = user else {
+        return Ok(login_first());
+    };
+    let transactions = fetch_transactions(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        Some(&user),
+        200,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let html = render_page(move || {
+        view! {
+            <Layout title="History" user=Some(user.clone())>
+                <History username=user transactions=transactions/>
+            </Layout>
+        }
+    });
+    Ok(html.into_response())
+}
+
+pub async fn settings_page(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Response, StatusCode> {
+    let Some((user, _)) = user else {
+        return Ok(login_first());
+    };
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let account = sqlx::query!(
+        "SELECT display_name, role FROM users WHERE username = ?;",
+        user
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let now = chrono::Utc::now().timestamp();
+    let sessions = sqlx::query!(
+        "SELECT COUNT(*) as count FROM auth_tokens WHERE username = ? AND expire_timestamp > ?;",
+        user,
+        now
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .count;
+    let html = render_page(move || {
+        view! {
+            <Layout title="Settings" user=Some(user.clone())>
+                <h1>Settings</h1>
+                <table>
+                    <tr><th>Username</th><td>{user}</td></tr>
+                    <tr><th>Display Name</th><td>{account.display_name}</td></tr>
+                    <tr><th>Role</th><td>{account.role}</td></tr>
+                    <tr><th>Active logins</th><td>{sessions}</td></tr>
+                </table>
+                <h2>Delete Account</h2>
+                <p>"Your money goes back to the treasury and can't be recovered"</p>
+                <form hx-post="/delete_account" hx-confirm="Delete your account for good?">
+                    <CsrfField/>
+                    <input type="number" name="otp" placeholder="Passcode" required/>
+                    <button class="button">Delete Account</button>
+                </form>
+            </Layout>
+        }
+    });
+    Ok(html.into_response())
+}
+
+/// The parts of the dashboard as they are now, one event each
+async fn render_updates(state: &App, username: &str) -> Vec<Result<SseEvent, Infallible>> {
+    let data = match state.db.acquire().await {
+        Ok(mut conn) => load_dashboard(&mut conn, username).await,
+        Err(err) => Err(err),
+    };
+    let Ok(data) = data else {
+        return Vec::new();
+    };
+    let DashboardData {
+        username,
+        balances,
+        pending,
+        history,
+        ..
+    } = data;
+    let balance = render(move || view! { <Balances balances=balances/> });
+    let pending = render(move || view! { <PendingApprovals pending=pending/> });
+    let history = render(move || view! { <History username=username transactions=history/> });
+    vec![
+        Ok(SseEvent::default().event("balance").data(&balance)),
+        Ok(SseEvent::default().event("pending").data(&pending)),
+        Ok(SseEvent::default().event("history").data(&history)),
+    ]
+}
+
+/// Live updates for the dashboard of the logged in user, sent whenever
+/// something about their money or their waiting transactions changes
+pub async fn live_updates(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let events = state.events.subscribe();
+    let updates = stream::unfold(
+        (events, state, user),
+        |(mut events, state, user)| async move {
+            loop {
+                match events.recv().await {
+                    Ok(Event::Changed(users)) if users.contains(&user) => break,
+                    // missed some, one of them might have been for us
+                    Err(RecvError::Lagged(_)) => break,
+                    Ok(_) => {}
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+            let updates = render_updates(&state, &user).await;
+            Some((stream::iter(updates), (events, state, user)))
+        },
+    )
+    .flatten();
+    Ok(Sse::new(updates).keep_alive(KeepAlive::default()))
+}
@@ -9,3 +9,18 @@ pub async fn get_displayname_from_username(app: &App, username: &str) -> eyre::R
     .await?;
     Ok(r.display_name)
 }
+
+pub async fn is_admin(app: &App, username: &str) -> eyre::Result<bool> {
+    let r = sqlx::query!("SELECT role FROM users WHERE username = ?;", username)
+        .fetch_one(&mut *app.db.acquire().await?)
+        .await?;
+    Ok(r.role == "admin")
+}
+
+/// Errors if the user doesn't exist
+pub async fn is_frozen(app: &App, username: &str) -> eyre::Result<bool> {
+    let r = sqlx::query!("SELECT frozen FROM users WHERE username = ?;", username)
+        .fetch_one(&mut *app.db.acquire().await?)
+        .await?;
+    Ok(r.frozen != 0)
+}
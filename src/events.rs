@@ -0,0 +1,64 @@
+use axum::extract::ws::{Message, WebSocket};
+use tokio::sync::broadcast::{self, error::RecvError, Receiver, Sender};
+
+/// How many events a slow listener may fall behind before it misses some
+const CAPACITY: usize = 256;
+
+/// Something that happened, for everyone listening. The websockets waiting on a
+/// transaction or hold and the live pages of the users all hear the same events.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// the transaction or hold `id` changed, `msg` is like "transaction_accepted"
+    Status { id: String, msg: String },
+    /// the balances, history or waiting transactions of these users changed
+    Changed(Vec<String>),
+}
+
+#[derive(Clone)]
+pub struct Events(Sender<Event>);
+
+impl Default for Events {
+    fn default() -> Self {
+        Events(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl Events {
+    /// Nobody listening is fine, the event is just gone then
+    pub fn publish(&self, event: Event) {
+        _ = self.0.send(event);
+    }
+
+    pub fn changed<S: AsRef<str>>(&self, users: impl IntoIterator<Item = S>) {
+        self.publish(Event::Changed(
+            users.into_iter().map(|u| u.as_ref().to_owned()).collect(),
+        ));
+    }
+
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.0.subscribe()
+    }
+}
+
+/// Sends the status message of `id` to the socket once it comes and closes it,
+/// gives up if the other side goes away first
+pub async fn forward_status(mut socket: WebSocket, mut events: Receiver<Event>, id: String) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(Event::Status { id: event_id, msg }) if event_id == id => {
+                    _ = socket.send(Message::Text(msg)).await;
+                    _ = socket.close().await;
+                    return;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+            msg = socket.recv() => {
+                if !matches!(msg, Some(Ok(_))) {
+                    return;
+                }
+            }
+        }
+    }
+}
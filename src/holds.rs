@@ -0,0 +1,421 @@
+use axum::{
+    extract::{Path, State, WebSocketUpgrade},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{
+    api::send_notification,
+    auth::AuthUser,
+    config,
+    db_utils::is_frozen,
+    events::forward_status,
+    ledger::{
+        approve_hold, capture_hold, default_currency, get_transaction_ttl, release_hold,
+        to_minor_units, TransferError,
+    },
+    money::Money,
+    status::HoldStatus,
+    util::{get_random_string, ApiRequest},
+    App,
+};
+
+/// Money can't stay on hold longer than this, afterwards it goes back to the buyer
+pub fn get_hold_ttl() -> Duration {
+    Duration::hours(config::get().ttl.hold_hours)
+}
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/request_hold", post(request_hold))
+        .route("/approve_hold/:id", post(approve))
+        .route("/decline_hold/:id", post(decline))
+        .route("/capture_hold/:id", post(capture))
+        .route("/release_hold/:id", post(release))
+        .route("/notify_hold/:id", get(notify_hold))
+        .route("/list_holds", post(list_holds))
+}
+
+#[derive(Deserialize, Debug)]
+struct RequestHold {
+    buyer: String,
+    name: String,
+    amount: Money,
+    #[serde(default = "default_currency")]
+    currency: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CaptureHold {
+    /// everything if not given
+    amount: Option<Money>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Hold {
+    pub id: String,
+    pub buyer: String,
+    pub seller: String,
+    pub name: String,
+    pub amount: Money,
+    pub captured: Money,
+    pub currency: String,
+    pub status: HoldStatus,
+    pub created: i64,
+}
+
+struct StoredHold {
+    buyer: String,
+    seller: String,
+    currency: String,
+    status: HoldStatus,
+}
+
+async fn fetch_hold(conn: &mut SqliteConnection, id: &str) -> Result<StoredHold, StatusCode> {
+    sqlx::query_as!(
+        StoredHold,
+        r#"SELECT buyer, seller, currency, status as "status: HoldStatus"
+           FROM holds WHERE id = ?;"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Tells the seller waiting on the hold socket what happened, as "hold_{status}"
+fn notify(state: &App, id: &str, status: HoldStatus) {
+    send_notification(state, id, format!("hold_{status}"));
+}
+
+/// Declines requests nobody answered and releases holds nobody captured in time.
+/// Returns the ids of the declined requests and everyone whose holds changed.
+async fn release_stale_holds(
+    conn: &mut SqliteConnection,
+) -> Result<(Vec<String>, Vec<String>), TransferError> {
+    let now = chrono::Utc::now();
+    let request_cutoff = (now - get_transaction_ttl()).timestamp();
+    let mut declined = Vec::new();
+    let mut users = Vec::new();
+    let requests = sqlx::query!(
+        "UPDATE holds SET status = ?1 WHERE status = ?2 AND created < ?3
+         RETURNING id, buyer, seller;",
+        HoldStatus::Declined,
+        HoldStatus::Requested,
+        request_cutoff
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for r in requests {
+        declined.push(r.id);
+        users.extend([r.buyer, r.seller]);
+    }
+    // sessions close their own holds
+    let hold_cutoff = (now - get_hold_ttl()).timestamp();
+    let stale = sqlx::query!(
+        "SELECT id, buyer, seller FROM holds WHERE status = ? AND approved < ?
+            AND id NOT IN (SELECT hold_id FROM sessions WHERE ended IS NULL);",
+        HoldStatus::Held,
+        hold_cutoff
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for r in stale {
+        release_hold(conn, &r.id).await?;
+        users.extend([r.buyer, r.seller]);
+    }
+    Ok((declined, users))
+}
+
+/// Gives back money that was held for too long
+pub(crate) async fn expire_holds(state: &App) {
+    let result = match state.db.begin().await {
+        Ok(mut tx) => match release_stale_holds(&mut tx).await {
+            Ok(ids) => tx.commit().await.map(|_| ids).map_err(TransferError::from),
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err.into()),
+    };
+    match result {
+        Ok((ids, users)) => {
+            for id in ids {
+                notify(state, &id, HoldStatus::Declined);
+            }
+            if !users.is_empty() {
+                state.events.changed(users);
+            }
+        }
+        Err(err) => println!("failed to release holds: {err}"),
+    }
+}
+
+/// Seller asks the buyer to put money on hold, returns the id of the hold
+async fn request_hold(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<RequestHold>,
+) -> Result<Json<String>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    if !data.amount.is_positive() || user == data.buyer {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    match is_frozen(&state, &user).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    match is_frozen(&state, &data.buyer).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::NOT_FOUND)?,
+    }
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let amount = to_minor_units(&mut conn, &data.currency, data.amount).await?;
+    let id = get_random_string(8);
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO holds (id, buyer, seller, name, currency, amount, status, created)
+         VALUES (?,?,?,?,?,?,?,?);",
+        id,
+        data.buyer,
+        user,
+        data.name,
+        data.currency,
+        amount,
+        HoldStatus::Requested,
+        now
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(id))
+}
+
+/// buyer puts the money on hold
+async fn approve(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let hold = fetch_hold(&mut tx, &id).await?;
+    if hold.buyer != user {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    if !matches!(is_frozen(&state, &hold.seller).await, Ok(false)) {
+        Err(StatusCode::FORBIDDEN)?;
+    }
+    approve_hold(&mut tx, &id, &user).await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    notify(&state, &id, HoldStatus::Held);
+    state.events.changed([&hold.buyer, &hold.seller]);
+    Ok(StatusCode::OK)
+}
+
+/// buyer says no
+async fn decline(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let r = sqlx::query!(
+        "UPDATE holds SET status = ? WHERE id = ? AND buyer = ? AND status = ?;",
+        HoldStatus::Declined,
+        id,
+        user,
+        HoldStatus::Requested
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::CONFLICT)?;
+    }
+    notify(&state, &id, HoldStatus::Declined);
+    Ok(StatusCode::OK)
+}
+
+/// Seller takes part or all of the held money, the rest goes back to the buyer.
+/// Returns the id of the transaction, null if nothing was captured.
+async fn capture(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+    ApiRequest(data): ApiRequest<CaptureHold>,
+) -> Result<Json<Option<String>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let hold = fetch_hold(&mut tx, &id).await?;
+    if hold.seller != user {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    let in_session = sqlx::query!(
+        "SELECT true FROM sessions WHERE hold_id = ? AND ended IS NULL;",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if in_session.is_some() {
+        Err(StatusCode::CONFLICT)?;
+    }
+    let amount = match data.amount {
+        Some(amount) => to_minor_units(&mut tx, &hold.currency, amount).await?,
+        None => {
+            sqlx::query!("SELECT amount FROM holds WHERE id = ?;", id)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .amount
+        }
+    };
+    let transaction = capture_hold(&mut tx, &id, amount).await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([&hold.buyer, &hold.seller]);
+    Ok(Json(transaction))
+}
+
+/// Seller gives all of the held money back, like when the item couldn't be dispensed
+async fn release(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let hold = fetch_hold(&mut tx, &id).await?;
+    if hold.seller != user {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    if !hold.status.can_become(HoldStatus::Released) {
+        Err(StatusCode::CONFLICT)?;
+    }
+    release_hold(&mut tx, &id).await?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([&hold.buyer, &hold.seller]);
+    Ok(StatusCode::OK)
+}
+
+/// Websocket that gets "hold_held" or "hold_declined" once the buyer answered
+async fn notify_hold(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let hold = fetch_hold(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        &id,
+    )
+    .await?;
+    if hold.seller != user && hold.buyer != user {
+        Err(StatusCode::UNAUTHORIZED)?;
+    }
+    let events = state.events.subscribe();
+    Ok(ws.on_upgrade(move |socket| forward_status(socket, events, id)))
+}
+
+/// The holds of the logged in user, as buyer or seller
+async fn list_holds(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<Hold>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let holds = get_holds(&mut conn, &user)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(holds))
+}
+
+pub async fn get_holds(conn: &mut SqliteConnection, username: &str) -> sqlx::Result<Vec<Hold>> {
+    let rows = sqlx::query!(
+        r#"SELECT id, buyer, seller, holds.name, amount, captured, holds.currency,
+            status as "status: HoldStatus", created, currencies.decimals
+           FROM holds JOIN currencies ON currencies.code = holds.currency
+           WHERE buyer = ?1 OR seller = ?1 ORDER BY created DESC LIMIT 50;"#,
+        username
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| Hold {
+            id: r.id,
+            buyer: r.buyer,
+            seller: r.seller,
+            name: r.name,
+            amount: Money::new(r.amount, r.decimals as u8),
+            captured: Money::new(r.captured, r.decimals as u8),
+            currency: r.currency,
+            status: r.status,
+            created: r.created,
+        })
+        .collect())
+}
@@ -0,0 +1,432 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::{AuthUser, RequestType},
+    layout::{render_page, Layout},
+    ledger::TransferError,
+    money::{Money, MoneyError},
+    status::TransactionStatus,
+    util::{format_timestamp, RequestTypeEnum},
+    App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new().route("/:id", get(get_invoice))
+}
+
+/// What a line of an invoice is for
+#[derive(sqlx::Type, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Item = 0,
+    /// a percentage of the items
+    Tax = 1,
+    /// a fixed amount on top
+    Fee = 2,
+}
+
+fn one() -> i64 {
+    1
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LineItem {
+    pub description: String,
+    #[serde(default = "one")]
+    pub quantity: i64,
+    pub unit_price: Money,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TaxLine {
+    pub description: String,
+    /// of the items, "7.5" for 7.5%
+    pub percent: Money,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FeeLine {
+    pub description: String,
+    pub amount: Money,
+}
+
+/// A line ready to be stored, all amounts in minor units
+pub(crate) struct NewLine {
+    kind: LineKind,
+    description: String,
+    quantity: Option<i64>,
+    unit_price: Option<i64>,
+    basis_points: Option<i64>,
+    amount: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InvoiceLine {
+    pub kind: LineKind,
+    pub description: String,
+    pub quantity: Option<i64>,
+    pub unit_price: Option<Money>,
+    pub percent: Option<Money>,
+    pub amount: Money,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Invoice {
+    pub id: String,
+    pub buyer: String,
+    pub seller: String,
+    pub name: String,
+    pub status: TransactionStatus,
+    pub timestamp: i64,
+    pub currency: String,
+    pub lines: Vec<InvoiceLine>,
+    /// the items without taxes and fees
+    pub subtotal: Money,
+    pub total: Money,
+}
+
+const OVERFLOW: TransferError = TransferError::InvalidAmount(MoneyError::Overflow);
+
+/// Checks the lines and works out their amounts, taxes are rounded to the nearest
+/// minor unit. Returns the lines and what they add up to.
+pub(crate) fn build_lines(
+    decimals: u8,
+    items: Vec<LineItem>,
+    taxes: Vec<TaxLine>,
+    fees: Vec<FeeLine>,
+) -> Result<(Vec<NewLine>, i64), TransferError> {
+    let mut lines = Vec::with_capacity(items.len() + taxes.len() + fees.len());
+    let mut subtotal: i64 = 0;
+    for item in items {
+        let unit_price = item.unit_price.rescale(decimals)?.minor();
+        if unit_price <= 0 || item.quantity <= 0 {
+            return Err(TransferError::InvalidAmount(MoneyError::Invalid));
+        }
+        let amount = unit_price.checked_mul(item.quantity).ok_or(OVERFLOW)?;
+        subtotal = subtotal.checked_add(amount).ok_or(OVERFLOW)?;
+        lines.push(NewLine {
+            kind: LineKind::Item,
+            description: item.description,
+            quantity: Some(item.quantity),
+            unit_price: Some(unit_price),
+            basis_points: None,
+            amount,
+        });
+    }
+    let mut total = subtotal;
+    for tax in taxes {
+        let basis_points = tax.percent.rescale(2)?.minor();
+        if !(0..=10_000).contains(&basis_points) {
+            return Err(TransferError::InvalidAmount(MoneyError::Invalid));
+        }
+        let amount = (subtotal as i128 * basis_points as i128 + 5_000) / 10_000;
+        let amount = i64::try_from(amount).map_err(|_| OVERFLOW)?;
+        total = total.checked_add(amount).ok_or(OVERFLOW)?;
+        lines.push(NewLine {
+            kind: LineKind::Tax,
+            description: tax.description,
+            quantity: None,
+            unit_price: None,
+            basis_points: Some(basis_points),
+            amount,
+        });
+    }
+    for fee in fees {
+        let amount = fee.amount.rescale(decimals)?.minor();
+        if amount <= 0 {
+            return Err(TransferError::InvalidAmount(MoneyError::Invalid));
+        }
+        total = total.checked_add(amount).ok_or(OVERFLOW)?;
+        lines.push(NewLine {
+            kind: LineKind::Fee,
+            description: fee.description,
+            quantity: None,
+            unit_price: None,
+            basis_points: None,
+            amount,
+        });
+    }
+    Ok((lines, total))
+}
+
+pub(crate) async fn save_lines(
+    conn: &mut SqliteConnection,
+    transaction_id: &str,
+    lines: &[NewLine],
+) -> sqlx::Result<()> {
+    for (position, line) in lines.iter().enumerate() {
+        let position = position as i64;
+        sqlx::query!(
+            "INSERT INTO invoice_lines (transaction_id, position, kind, description, quantity,
+                unit_price, basis_points, amount)
+             VALUES (?,?,?,?,?,?,?,?);",
+            transaction_id,
+            position,
+            line.kind,
+            line.description,
+            line.quantity,
+            line.unit_price,
+            line.basis_points,
+            line.amount
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// The whole invoice of a transaction, `None` if it doesn't exist
+pub async fn fetch_invoice(conn: &mut SqliteConnection, id: &str) -> sqlx::Result<Option<Invoice>> {
+    let transaction = sqlx::query!(
+        r#"SELECT buyer, seller, transactions.name, amount, currency, decimals,
+            status as "status: TransactionStatus", timestamp
+           FROM transactions JOIN currencies ON currencies.code = transactions.currency
+           WHERE id = ?;"#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(t) = transaction else {
+        return Ok(None);
+    };
+    let decimals = t.decimals as u8;
+    let rows = sqlx::query!(
+        r#"SELECT kind as "kind: LineKind", description, quantity, unit_price, basis_points, amount
+           FROM invoice_lines WHERE transaction_id = ? ORDER BY position;"#,
+        id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let lines: Vec<InvoiceLine> = if rows.is_empty() {
+        vec![InvoiceLine {
+            kind: LineKind::Item,
+            description: t.name.clone(),
+            quantity: Some(1),
+            unit_price: Some(Money::new(t.amount, decimals)),
+            percent: None,
+            amount: Money::new(t.amount, decimals),
+        }]
+    } else {
+        rows.into_iter()
+            .map(|r| InvoiceLine {
+                kind: r.kind,
+                description: r.description,
+                quantity: r.quantity,
+                unit_price: r.unit_price.map(|p| Money::new(p, decimals)),
+                percent: r.basis_points.map(|b| Money::new(b, 2)),
+                amount: Money::new(r.amount, decimals),
+            })
+            .collect()
+    };
+    let subtotal = lines
+        .iter()
+        .filter(|l| l.kind == LineKind::Item)
+        .map(|l| l.amount.minor())
+        .sum();
+    Ok(Some(Invoice {
+        id: id.to_owned(),
+        buyer: t.buyer,
+        seller: t.seller,
+        name: t.name,
+        status: t.status,
+        timestamp: t.timestamp,
+        currency: t.currency,
+        lines,
+        subtotal: Money::new(subtotal, decimals),
+        total: Money::new(t.amount, decimals),
+    }))
+}
+
+/// The invoice as JSON, or as a receipt page when asked for html.
+/// Only the buyer and the seller get to see it.
+async fn get_invoice(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let invoice = fetch_invoice(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        &id,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    if invoice.buyer != user && invoice.seller != user {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    Ok(match req_type {
+        RequestTypeEnum::Json => Json(invoice).into_response(),
+        RequestTypeEnum::Html => receipt_page(user, invoice).into_response(),
+    })
+}
+
+fn receipt_page(user: String, invoice: Invoice) -> Html<String> {
+    render_page(move || {
+        let currency = invoice.currency;
+        let lines = invoice
+            .lines
+            .into_iter()
+            .map(|l| {
+                let detail = match l.kind {
+                    LineKind::Item => match (l.quantity, l.unit_price) {
+                        (Some(q), Some(p)) => format!("{q} x {p}"),
+                        _ => String::new(),
+                    },
+                    LineKind::Tax => l.percent.map(|p| format!("{p}%")).unwrap_or_default(),
+                    LineKind::Fee => String::new(),
+                };
+                view! {
+                    <tr>
+                        <td>{l.description}</td>
+                        <td>{detail}</td>
+                        <td class="text-right">{l.amount.to_string()}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        view! {
+            <Layout title="Receipt" user=Some(user)>
+                <h1>"Receipt "{invoice.id}</h1>
+                <p>{invoice.name}</p>
+                <p>"From "{invoice.seller}" to "{invoice.buyer}</p>
+                <p>{format_timestamp(invoice.timestamp)}" - "{invoice.status.as_str()}</p>
+                <table>
+                    <tbody>{lines}</tbody>
+                    <tfoot>
+                        <tr>
+                            <td>Subtotal</td>
+                            <td></td>
+                            <td class="text-right">{invoice.subtotal.to_string()}</td>
+                        </tr>
+                        <tr>
+                            <th>Total</th>
+                            <td></td>
+                            <th class="text-right">{invoice.total.to_string()}" "{currency}</th>
+                        </tr>
+                    </tfoot>
+                </table>
+            </Layout>
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(quantity: i64, unit_price: &str) -> LineItem {
+        LineItem {
+            description: "Item".to_owned(),
+            quantity,
+            unit_price: unit_price.parse().unwrap(),
+        }
+    }
+
+    fn tax(percent: &str) -> TaxLine {
+        TaxLine {
+            description: "Tax".to_owned(),
+            percent: percent.parse().unwrap(),
+        }
+    }
+
+    fn fee(amount: &str) -> FeeLine {
+        FeeLine {
+            description: "Fee".to_owned(),
+            amount: amount.parse().unwrap(),
+        }
+    }
+
+    fn amounts(lines: &[NewLine]) -> Vec<i64> {
+        lines.iter().map(|l| l.amount).collect()
+    }
+
+    #[test]
+    fn tax_rounds_to_the_nearest_minor_unit() {
+        // 7.5% of 1.01 is 0.07575
+        let (lines, total) =
+            build_lines(2, vec![item(1, "1.01")], vec![tax("7.5")], vec![]).unwrap();
+        assert_eq!(amounts(&lines), [101, 8]);
+        assert_eq!(total, 109);
+        // 5% of 1.05 is 0.0525
+        let (lines, _) = build_lines(2, vec![item(1, "1.05")], vec![tax("5")], vec![]).unwrap();
+        assert_eq!(amounts(&lines), [105, 5]);
+        // halves round up, 1% of 1.50 is 0.015
+        let (lines, _) = build_lines(2, vec![item(1, "1.5")], vec![tax("1")], vec![]).unwrap();
+        assert_eq!(amounts(&lines), [150, 2]);
+        // without decimals, 19% of 3 coins is 0.57
+        let (lines, total) = build_lines(0, vec![item(3, "1")], vec![tax("19")], vec![]).unwrap();
+        assert_eq!(amounts(&lines), [3, 1]);
+        assert_eq!(total, 4);
+    }
+
+    #[test]
+    fn lines_add_up() {
+        let (lines, total) = build_lines(
+            2,
+            vec![item(2, "1.25"), item(1, "0.99")],
+            vec![tax("10"), tax("2.5")],
+            vec![fee("0.5")],
+        )
+        .unwrap();
+        // taxes are both of the 3.49 of items, not of each other
+        assert_eq!(amounts(&lines), [250, 99, 35, 9, 50]);
+        assert_eq!(lines[0].quantity, Some(2));
+        assert_eq!(lines[0].unit_price, Some(125));
+        assert_eq!(lines[3].basis_points, Some(250));
+        assert_eq!(total, 250 + 99 + 35 + 9 + 50);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let invalid = |r: Result<(Vec<NewLine>, i64), TransferError>| {
+            matches!(r, Err(TransferError::InvalidAmount(_)))
+        };
+        assert!(invalid(build_lines(2, vec![item(0, "1")], vec![], vec![])));
+        assert!(invalid(build_lines(2, vec![item(1, "-1")], vec![], vec![])));
+        assert!(invalid(build_lines(
+            2,
+            vec![item(1, "0.001")],
+            vec![],
+            vec![]
+        )));
+        assert!(invalid(build_lines(
+            2,
+            vec![item(1, "1")],
+            vec![tax("100.01")],
+            vec![]
+        )));
+        assert!(invalid(build_lines(
+            2,
+            vec![item(1, "1")],
+            vec![],
+            vec![fee("0")]
+        )));
+        let huge = item(i64::MAX, "1");
+        assert!(invalid(build_lines(
+            0,
+            vec![huge, item(1, "1")],
+            vec![],
+            vec![]
+        )));
+    }
+}
@@ -0,0 +1,125 @@
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use leptos::{ssr::render_to_string as render, *};
+
+use crate::{
+    assets::asset_url,
+    csrf::{csrf_token, CSRF_HEADER},
+};
+
+/// Version of htmx the files in assets/ should be
+const HTMX_VERSION: &str = "1.9.4";
+
+/// Message for the next page that gets rendered, like "Logged out"
+const FLASH_COOKIE: &str = "Money-Flash";
+
+/// The embedded copy of a file from the htmx dist folder,
+/// or unpkg if it wasn't put in assets/ before building
+fn htmx_url(file: &str) -> String {
+    asset_url(file)
+        .unwrap_or_else(|| format!("https://unpkg.com/htmx.org@{HTMX_VERSION}/dist/{file}"))
+}
+
+#[component]
+pub fn page_head(#[prop(into)] title: String) -> impl IntoView {
+    view! {
+        <head>
+            <title>{title}" - Schmervices"</title>
+            <script type="text/javascript" src=htmx_url("htmx.min.js")></script>
+            <script type="text/javascript" src=htmx_url("ext/sse.js")></script>
+            <meta charset="UTF-8"></meta>
+            <meta name="viewport" content="width=device-width, initial-scale=1.0"></meta>
+            <link href=asset_url("main.css") rel="stylesheet"></link>
+        </head>
+    }
+}
+
+#[component]
+fn nav(user: Option<String>) -> impl IntoView {
+    let links = match user {
+        Some(user) => view! {
+            <a href="/dashboard">Dashboard</a>
+            <a href="/history">History</a>
+            <a href="/shops">Shops</a>
+            <a href="/schmervices">Schmervices</a>
+            <a href="/settings">{user}</a>
+            <button hx-post="/logout" class="button">Logout</button>
+        }
+        .into_view(),
+        None => view! {
+            <a href="/shops">Shops</a>
+            <a href="/schmervices">Schmervices</a>
+            <a href="/login">Login</a>
+            <a href="/register" class="button">Signup</a>
+        }
+        .into_view(),
+    };
+    view! { <nav><a href="/"><b>Schmervices</b></a>{links}</nav> }
+}
+
+/// The frame every full page is rendered in, `user` is who is logged in.
+/// htmx sends the csrf token with every request.
+#[component]
+pub fn layout(
+    #[prop(into)] title: String,
+    user: Option<String>,
+    #[prop(optional_no_strip)] flash: Option<String>,
+    children: Children,
+) -> impl IntoView {
+    let hx_headers = csrf_token().map(|csrf| format!(r#"{{"{CSRF_HEADER}": "{csrf}"}}"#));
+    view! {
+        <PageHead title=title/>
+        <body hx-headers=hx_headers>
+            <Nav user=user/>
+            {flash.map(|msg| view! { <p class="flash">{msg}</p> })}
+            <main>{children()}</main>
+            <footer>
+                <a href="/lua/schmervice_lib.lua">Lua library</a>
+            </footer>
+        </body>
+    }
+}
+
+/// Renders a whole document, for anything that isn't an htmx fragment
+pub fn render_page<F, N>(f: F) -> Html<String>
+where
+    F: FnOnce() -> N + 'static,
+    N: IntoView,
+{
+    Html::from("<!DOCTYPE html>\n".to_owned() + &render(f))
+}
+
+/// Leaves a message for the next page
+pub fn set_flash(jar: CookieJar, msg: &str) -> CookieJar {
+    let mut cookie = Cookie::new(FLASH_COOKIE, msg.to_owned());
+    cookie.set_path("/");
+    cookie.set_http_only(Some(true));
+    jar.add(cookie)
+}
+
+/// The message left for this page, if any, it is only shown once
+pub fn take_flash(jar: CookieJar) -> (CookieJar, Option<String>) {
+    let msg = jar.get(FLASH_COOKIE).map(|c| c.value().to_owned());
+    match msg {
+        Some(msg) => {
+            let mut cookie = Cookie::from(FLASH_COOKIE);
+            cookie.set_path("/");
+            (jar.remove(cookie), Some(msg))
+        }
+        None => (jar, None),
+    }
+}
+
+/// Sends the browser to another page, htmx has to be told with a header
+/// since it would follow a redirect and swap the whole page into its target
+pub fn go_to(headers: &HeaderMap, to: &str) -> Response {
+    if headers.contains_key("HX-Request") {
+        let location = HeaderValue::from_str(to).unwrap_or(HeaderValue::from_static("/"));
+        (StatusCode::OK, [("HX-Redirect", location)]).into_response()
+    } else {
+        Redirect::to(to).into_response()
+    }
+}
@@ -0,0 +1,802 @@
+use std::fmt::Display;
+
+use axum::http::StatusCode;
+use chrono::Duration;
+use sqlx::SqliteConnection;
+
+use crate::{
+    config,
+    money::{Money, MoneyError, MAX_DECIMALS},
+    status::{HoldStatus, SubscriptionStatus, TransactionStatus},
+    util::get_random_string,
+};
+
+/// System account that holds money which doesn't belong to any player
+pub const TREASURY: &str = "treasury";
+/// Used whenever a request doesn't say which currency it means
+pub const DEFAULT_CURRENCY: &str = "COIN";
+
+pub fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_owned()
+}
+
+/// How long the buyer has to answer a transaction request
+pub fn get_transaction_ttl() -> Duration {
+    Duration::minutes(config::get().ttl.transaction_minutes)
+}
+
+#[derive(Debug)]
+pub enum TransferError {
+    InsufficientFunds,
+    UnknownAccount,
+    UnknownCurrency,
+    InvalidAmount(MoneyError),
+    IllegalTransition,
+    /// tried to capture more than the hold has
+    ExceedsHold,
+    Database(sqlx::Error),
+}
+
+impl From<MoneyError> for TransferError {
+    fn from(value: MoneyError) -> Self {
+        TransferError::InvalidAmount(value)
+    }
+}
+
+impl From<sqlx::Error> for TransferError {
+    fn from(value: sqlx::Error) -> Self {
+        TransferError::Database(value)
+    }
+}
+
+impl Display for TransferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferError::InsufficientFunds => write!(f, "Insufficient Funds"),
+            TransferError::UnknownAccount => write!(f, "Unknown Account"),
+            TransferError::UnknownCurrency => write!(f, "Unknown Currency"),
+            TransferError::InvalidAmount(e) => write!(f, "{e}"),
+            TransferError::IllegalTransition => {
+                write!(f, "Transaction can't change to that status")
+            }
+            TransferError::ExceedsHold => write!(f, "Amount is more than the hold"),
+            TransferError::Database(e) => write!(f, "Database Error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+impl From<TransferError> for StatusCode {
+    fn from(value: TransferError) -> Self {
+        match value {
+            TransferError::InsufficientFunds => StatusCode::PAYMENT_REQUIRED,
+            TransferError::UnknownAccount => StatusCode::NOT_FOUND,
+            TransferError::UnknownCurrency => StatusCode::NOT_FOUND,
+            TransferError::InvalidAmount(_) => StatusCode::BAD_REQUEST,
+            TransferError::IllegalTransition => StatusCode::CONFLICT,
+            TransferError::ExceedsHold => StatusCode::BAD_REQUEST,
+            TransferError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct Balance {
+    pub currency: String,
+    pub amount: Money,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct MoneySupply {
+    pub currency: String,
+    /// all money held by accounts, including the treasury and money on hold
+    pub total: Money,
+    pub treasury: Money,
+    /// sum of everything minted minus everything burned
+    pub issued: Money,
+    pub reconciles: bool,
+}
+
+pub async fn currency_exists(conn: &mut SqliteConnection, currency: &str) -> sqlx::Result<bool> {
+    let r = sqlx::query!("SELECT true FROM currencies WHERE code = ?;", currency)
+        .fetch_optional(conn)
+        .await?;
+    Ok(r.is_some())
+}
+
+pub async fn currency_decimals(
+    conn: &mut SqliteConnection,
+    currency: &str,
+) -> Result<u8, TransferError> {
+    let r = sqlx::query!("SELECT decimals FROM currencies WHERE code = ?;", currency)
+        .fetch_optional(conn)
+        .await?
+        .ok_or(TransferError::UnknownCurrency)?;
+    Ok(r.decimals as u8)
+}
+
+/// Converts an amount from a request into the smallest unit of the currency,
+/// which is what everything in the db and the ledger works with
+pub async fn to_minor_units(
+    conn: &mut SqliteConnection,
+    currency: &str,
+    amount: Money,
+) -> Result<i64, TransferError> {
+    let decimals = currency_decimals(conn, currency).await?;
+    Ok(amount.rescale(decimals)?.minor())
+}
+
+pub async fn get_balances(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> sqlx::Result<Vec<Balance>> {
+    let rows = sqlx::query!(
+        "SELECT currency, amount, decimals FROM balances
+         INNER JOIN currencies ON code = currency
+         WHERE username = ? ORDER BY currency;",
+        username
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| Balance {
+            currency: r.currency,
+            amount: Money::new(r.amount, r.decimals as u8),
+        })
+        .collect())
+}
+
+/// Adds money to a balance, creating it if the account never held that currency
+async fn credit(
+    conn: &mut SqliteConnection,
+    username: &str,
+    currency: &str,
+    amount: i64,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO balances (username, currency, amount) VALUES (?,?,?)
+         ON CONFLICT (username, currency) DO UPDATE SET amount = amount + excluded.amount;",
+        username,
+        currency,
+        amount
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Takes money from a balance, fails instead of going negative
+async fn debit(
+    conn: &mut SqliteConnection,
+    username: &str,
+    currency: &str,
+    amount: i64,
+) -> Result<(), TransferError> {
+    let taken = sqlx::query!(
+        "UPDATE balances SET amount = amount - ?1
+         WHERE username = ?2 AND currency = ?3 AND amount >= ?1;",
+        amount,
+        username,
+        currency
+    )
+    .execute(conn)
+    .await?;
+    if taken.rows_affected() == 0 && amount > 0 {
+        return Err(TransferError::InsufficientFunds);
+    }
+    Ok(())
+}
+
+/// Moves money without recording it, callers have to record it themselves.
+/// Run this inside a db transaction so a failed record doesn't lose money.
+pub async fn move_money(
+    conn: &mut SqliteConnection,
+    from: &str,
+    to: &str,
+    currency: &str,
+    amount: i64,
+) -> Result<(), TransferError> {
+    if amount < 0 {
+        return Err(TransferError::InsufficientFunds);
+    }
+    if !currency_exists(conn, currency).await? {
+        return Err(TransferError::UnknownCurrency);
+    }
+    let exists = sqlx::query!("SELECT true FROM users WHERE username = ?;", to)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if exists.is_none() {
+        return Err(TransferError::UnknownAccount);
+    }
+    debit(conn, from, currency, amount).await?;
+    credit(conn, to, currency, amount).await?;
+    Ok(())
+}
+
+/// Moves money from `from` to `to` and records it as an accepted transaction.
+/// Run this inside a db transaction so a failed insert doesn't lose money.
+pub async fn record_transfer(
+    conn: &mut SqliteConnection,
+    from: &str,
+    to: &str,
+    name: &str,
+    currency: &str,
+    amount: i64,
+) -> Result<String, TransferError> {
+    move_money(conn, from, to, currency, amount).await?;
+    record_transaction(conn, from, to, name, currency, amount).await
+}
+
+/// Writes an accepted transaction into the history without moving any money
+async fn record_transaction(
+    conn: &mut SqliteConnection,
+    from: &str,
+    to: &str,
+    name: &str,
+    currency: &str,
+    amount: i64,
+) -> Result<String, TransferError> {
+    let id = get_random_string(8);
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO transactions (id, buyer, seller, name, amount, status, timestamp, currency)
+         VALUES (?,?,?,?,?,?,?,?)",
+        id,
+        from,
+        to,
+        name,
+        amount,
+        TransactionStatus::Accepted,
+        now,
+        currency
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(id)
+}
+
+/// What `amount` of a currency with `from_decimals` is worth at `rate` in one with
+/// `to_decimals`, both in minor units. The rate is for whole units, so this also shifts
+/// by the difference in decimals. Always rounds in favor of the treasury so
+/// conversions can't create money.
+fn exchange(
+    amount: i64,
+    from_decimals: u8,
+    rate: Money,
+    to_decimals: u8,
+) -> Result<i64, MoneyError> {
+    let numerator = i128::from(amount)
+        .checked_mul(rate.minor().into())
+        .and_then(|n| n.checked_mul(10i128.pow(to_decimals.into())))
+        .ok_or(MoneyError::Overflow)?;
+    let denominator = 10i128.pow(u32::from(from_decimals) + u32::from(rate.decimals()));
+    i64::try_from(numerator.div_euclid(denominator)).map_err(|_| MoneyError::Overflow)
+}
+
+/// Swaps `amount` of `from` for the equivalent in `to` with the treasury,
+/// using the exchange rate set by an admin. Returns how much `to` was received.
+pub async fn convert_currency(
+    conn: &mut SqliteConnection,
+    username: &str,
+    from: &str,
+    to: &str,
+    amount: Money,
+) -> Result<Money, TransferError> {
+    let rate = sqlx::query!(
+        "SELECT rate, rate_decimals FROM exchange_rates
+         WHERE from_currency = ? AND to_currency = ?;",
+        from,
+        to
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(TransferError::UnknownCurrency)?;
+    let rate = Money::new(rate.rate, rate.rate_decimals as u8);
+    let from_decimals = currency_decimals(conn, from).await?;
+    let to_decimals = currency_decimals(conn, to).await?;
+    let amount = amount.rescale(from_decimals)?.minor();
+    let received = exchange(amount, from_decimals, rate, to_decimals)?;
+    let name = format!("Exchange {from} to {to}");
+    record_transfer(conn, username, TREASURY, &name, from, amount).await?;
+    record_transfer(conn, TREASURY, username, &name, to, received).await?;
+    Ok(Money::new(received, to_decimals))
+}
+
+/// Changes the status of a transaction, but only if it's still `from`.
+/// Fails for transitions the state machine doesn't allow
+pub async fn set_status(
+    conn: &mut SqliteConnection,
+    id: &str,
+    from: TransactionStatus,
+    to: TransactionStatus,
+) -> Result<(), TransferError> {
+    if !from.can_become(to) {
+        return Err(TransferError::IllegalTransition);
+    }
+    let updated = sqlx::query!(
+        "UPDATE transactions SET status = ? WHERE id = ? AND status = ?;",
+        to,
+        id,
+        from
+    )
+    .execute(conn)
+    .await?;
+    if updated.rows_affected() == 0 {
+        // someone else changed it first
+        return Err(TransferError::IllegalTransition);
+    }
+    Ok(())
+}
+
+/// Rejects every waiting transaction the user is part of, returns their ids
+pub async fn reject_pending_transactions(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> sqlx::Result<Vec<String>> {
+    let ids = sqlx::query!(
+        "UPDATE transactions SET status = ?1
+         WHERE status = ?2 AND (buyer = ?3 OR seller = ?3)
+         RETURNING id;",
+        TransactionStatus::Rejected,
+        TransactionStatus::Pending,
+        username
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(ids.into_iter().map(|r| r.id).collect())
+}
+
+/// Expires every transaction that waited longer than the ttl,
+/// returns their ids and everyone that was part of one
+pub async fn expire_stale_transactions(
+    conn: &mut SqliteConnection,
+) -> sqlx::Result<(Vec<String>, Vec<String>)> {
+    let cutoff = (chrono::Utc::now() - get_transaction_ttl()).timestamp();
+    let expired = sqlx::query!(
+        "UPDATE transactions SET status = ?1
+         WHERE status = ?2 AND timestamp < ?3
+         RETURNING id, buyer, seller;",
+        TransactionStatus::Expired,
+        TransactionStatus::Pending,
+        cutoff
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut ids = Vec::with_capacity(expired.len());
+    let mut users = Vec::new();
+    for r in expired {
+        ids.push(r.id);
+        users.extend([r.buyer, r.seller]);
+    }
+    Ok((ids, users))
+}
+
+/// Deletes the account, its money goes to the treasury and its name is replaced
+/// in the transaction history so the ledger still adds up.
+/// Returns the ids of the pending transactions that got rejected.
+pub async fn delete_account(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> Result<Vec<String>, TransferError> {
+    let user = sqlx::query!("SELECT role FROM users WHERE username = ?;", username)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(TransferError::UnknownAccount)?;
+    if user.role == "system" {
+        return Err(TransferError::UnknownAccount);
+    }
+    let rejected = reject_pending_transactions(conn, username).await?;
+    release_holds_of(conn, username).await?;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = ?1
+         WHERE status IN (?2, ?3, ?4) AND (buyer = ?5 OR seller = ?5);",
+        SubscriptionStatus::Cancelled,
+        SubscriptionStatus::Requested,
+        SubscriptionStatus::Active,
+        SubscriptionStatus::PastDue,
+        username
+    )
+    .execute(&mut *conn)
+    .await?;
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "UPDATE sessions SET ended = ?1, cost = 0
+         WHERE ended IS NULL AND (owner = ?2 OR username = ?2);",
+        now,
+        username
+    )
+    .execute(&mut *conn)
+    .await?;
+    for balance in get_balances(conn, username).await? {
+        if balance.amount.is_positive() {
+            record_transfer(
+                conn,
+                username,
+                TREASURY,
+                "Account closed",
+                &balance.currency,
+                balance.amount.minor(),
+            )
+            .await?;
+        }
+    }
+    sqlx::query!("DELETE FROM balances WHERE username = ?;", username)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM auth_tokens WHERE username = ?;", username)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM products WHERE seller = ?;", username)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM shops WHERE owner = ?;", username)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM schmervices WHERE owner = ?;", username)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!(
+        "UPDATE schmervices SET in_use_by = NULL, claimed_at = NULL WHERE in_use_by = ?;",
+        username
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!("DELETE FROM users WHERE username = ?;", username)
+        .execute(&mut *conn)
+        .await?;
+
+    // ':' is not allowed in usernames so this can never clash with a real account
+    let pseudonym = format!("deleted:{}", get_random_string(8));
+    sqlx::query!(
+        "UPDATE transactions SET buyer = ? WHERE buyer = ?;",
+        pseudonym,
+        username
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "UPDATE transactions SET seller = ? WHERE seller = ?;",
+        pseudonym,
+        username
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(rejected)
+}
+
+/// Every stored amount by table and column, with the condition that picks
+/// the ones in the currency `?1`
+const AMOUNT_COLUMNS: &[(&str, &str, &str)] = &[
+    ("balances", "amount", "currency = ?1"),
+    ("transactions", "amount", "currency = ?1"),
+    ("supply_changes", "amount", "currency = ?1"),
+    ("products", "price", "currency = ?1"),
+    ("holds", "amount", "currency = ?1"),
+    ("holds", "captured", "currency = ?1"),
+    ("schmervices", "rate", "currency = ?1"),
+    ("sessions", "rate", "currency = ?1"),
+    ("sessions", "cost", "currency = ?1"),
+    ("subscriptions", "amount", "currency = ?1"),
+    (
+        "invoice_lines",
+        "unit_price",
+        "transaction_id IN (SELECT id FROM transactions WHERE currency = ?1)",
+    ),
+    (
+        "invoice_lines",
+        "amount",
+        "transaction_id IN (SELECT id FROM transactions WHERE currency = ?1)",
+    ),
+    ("payment_links", "amount", "currency = ?1"),
+];
+
+/// Gives a currency more decimals and scales everything stored in it to match,
+/// so "2" stays 2 coins but "2.5" becomes possible. Only goes up, going down could lose money.
+pub async fn raise_currency_decimals(
+    conn: &mut SqliteConnection,
+    currency: &str,
+    decimals: u8,
+) -> Result<(), TransferError> {
+    let current = currency_decimals(conn, currency).await?;
+    if decimals <= current || decimals > MAX_DECIMALS {
+        return Err(TransferError::InvalidAmount(MoneyError::TooPrecise));
+    }
+    let factor = 10i64.pow((decimals - current).into());
+    // sqlite turns integers that overflow into floats instead of failing
+    let limit = i64::MAX / factor;
+    for (table, column, filter) in AMOUNT_COLUMNS {
+        let too_large: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM {table} WHERE {filter} AND ABS({column}) > ?2;"
+        ))
+        .bind(currency)
+        .bind(limit)
+        .fetch_one(&mut *conn)
+        .await?;
+        if too_large > 0 {
+            return Err(TransferError::InvalidAmount(MoneyError::Overflow));
+        }
+    }
+    for (table, column, filter) in AMOUNT_COLUMNS {
+        sqlx::query(&format!(
+            "UPDATE {table} SET {column} = {column} * ?2 WHERE {filter};"
+        ))
+        .bind(currency)
+        .bind(factor)
+        .execute(&mut *conn)
+        .await?;
+    }
+    sqlx::query!(
+        "UPDATE currencies SET decimals = ? WHERE code = ?;",
+        decimals,
+        currency
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Takes `amount` from the buyer and keeps it aside until the seller captures it
+/// or it gets released. Returns the id of the hold.
+pub async fn place_hold(
+    conn: &mut SqliteConnection,
+    buyer: &str,
+    seller: &str,
+    name: &str,
+    currency: &str,
+    amount: i64,
+) -> Result<String, TransferError> {
+    if amount <= 0 {
+        return Err(TransferError::InvalidAmount(MoneyError::Invalid));
+    }
+    if !currency_exists(conn, currency).await? {
+        return Err(TransferError::UnknownCurrency);
+    }
+    let exists = sqlx::query!("SELECT true FROM users WHERE username = ?;", seller)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if exists.is_none() {
+        return Err(TransferError::UnknownAccount);
+    }
+    debit(conn, buyer, currency, amount).await?;
+    let id = get_random_string(8);
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO holds (id, buyer, seller, name, currency, amount, status, created, approved)
+         VALUES (?,?,?,?,?,?,?,?,?);",
+        id,
+        buyer,
+        seller,
+        name,
+        currency,
+        amount,
+        HoldStatus::Held,
+        now,
+        now
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(id)
+}
+
+/// The buyer agrees to a requested hold, this is when the money gets taken
+pub async fn approve_hold(
+    conn: &mut SqliteConnection,
+    id: &str,
+    buyer: &str,
+) -> Result<(), TransferError> {
+    let now = chrono::Utc::now().timestamp();
+    let hold = sqlx::query!(
+        "UPDATE holds SET status = ?1, approved = ?2 WHERE id = ?3 AND buyer = ?4 AND status = ?5
+         RETURNING currency, amount;",
+        HoldStatus::Held,
+        now,
+        id,
+        buyer,
+        HoldStatus::Requested
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(TransferError::IllegalTransition)?;
+    debit(conn, buyer, &hold.currency, hold.amount).await
+}
+
+struct HeldMoney {
+    buyer: String,
+    seller: String,
+    name: String,
+    currency: String,
+    amount: i64,
+}
+
+/// Marks the hold as done, fails if it isn't held anymore
+async fn close_hold(
+    conn: &mut SqliteConnection,
+    id: &str,
+    status: HoldStatus,
+    captured: i64,
+) -> Result<HeldMoney, TransferError> {
+    if !HoldStatus::Held.can_become(status) {
+        return Err(TransferError::IllegalTransition);
+    }
+    sqlx::query_as!(
+        HeldMoney,
+        "UPDATE holds SET status = ?1, captured = ?2 WHERE id = ?3 AND status = ?4
+         RETURNING buyer, seller, name, currency, amount;",
+        status,
+        captured,
+        id,
+        HoldStatus::Held
+    )
+    .fetch_optional(conn)
+    .await?
+    .ok_or(TransferError::IllegalTransition)
+}
+
+/// Gives `amount` of the hold to the seller and the rest back to the buyer.
+/// Returns the id of the recorded transaction, None if nothing was captured.
+pub async fn capture_hold(
+    conn: &mut SqliteConnection,
+    id: &str,
+    amount: i64,
+) -> Result<Option<String>, TransferError> {
+    if amount < 0 {
+        return Err(TransferError::InvalidAmount(MoneyError::Invalid));
+    }
+    let hold = close_hold(conn, id, HoldStatus::Captured, amount).await?;
+    if amount > hold.amount {
+        return Err(TransferError::ExceedsHold);
+    }
+    credit(conn, &hold.buyer, &hold.currency, hold.amount - amount).await?;
+    if amount == 0 {
+        return Ok(None);
+    }
+    credit(conn, &hold.seller, &hold.currency, amount).await?;
+    let transaction = record_transaction(
+        conn,
+        &hold.buyer,
+        &hold.seller,
+        &hold.name,
+        &hold.currency,
+        amount,
+    )
+    .await?;
+    Ok(Some(transaction))
+}
+
+/// Gives all of the hold back to the buyer
+pub async fn release_hold(conn: &mut SqliteConnection, id: &str) -> Result<(), TransferError> {
+    let hold = close_hold(conn, id, HoldStatus::Released, 0).await?;
+    credit(conn, &hold.buyer, &hold.currency, hold.amount).await?;
+    Ok(())
+}
+
+/// Releases every hold the user is part of, as buyer or seller
+async fn release_holds_of(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> Result<(), TransferError> {
+    let ids = sqlx::query!(
+        "SELECT id FROM holds WHERE status = ?1 AND (buyer = ?2 OR seller = ?2);",
+        HoldStatus::Held,
+        username
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for r in ids {
+        release_hold(conn, &r.id).await?;
+    }
+    Ok(())
+}
+
+/// Creates (positive amount) or destroys (negative amount) money in the treasury
+pub async fn change_money_supply(
+    conn: &mut SqliteConnection,
+    admin: &str,
+    currency: &str,
+    amount: i64,
+    reason: &str,
+) -> Result<(), TransferError> {
+    if !currency_exists(conn, currency).await? {
+        return Err(TransferError::UnknownCurrency);
+    }
+    if amount >= 0 {
+        credit(conn, TREASURY, currency, amount).await?;
+    } else {
+        debit(conn, TREASURY, currency, -amount).await?;
+    }
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO supply_changes (admin, amount, reason, timestamp, currency)
+         VALUES (?,?,?,?,?);",
+        admin,
+        amount,
+        reason,
+        now,
+        currency
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_money_supply(conn: &mut SqliteConnection) -> sqlx::Result<Vec<MoneySupply>> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            code,
+            decimals,
+            (SELECT COALESCE(SUM(amount), 0) FROM balances WHERE currency = code)
+            + (SELECT COALESCE(SUM(amount), 0) FROM holds WHERE currency = code AND status = ?2)
+                as "total!: i64",
+            (SELECT amount FROM balances WHERE currency = code AND username = ?1) as "treasury?: i64",
+            (SELECT COALESCE(SUM(amount), 0) FROM supply_changes WHERE currency = code) as "issued!: i64"
+           FROM currencies ORDER BY code;"#,
+        TREASURY,
+        HoldStatus::Held
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| MoneySupply {
+            currency: r.code,
+            total: Money::new(r.total, r.decimals as u8),
+            treasury: Money::new(r.treasury.unwrap_or_default(), r.decimals as u8),
+            issued: Money::new(r.issued, r.decimals as u8),
+            reconciles: r.total == r.issued,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(rate: &str) -> Money {
+        rate.parse().unwrap()
+    }
+
+    #[test]
+    fn exchange_rounds_down() {
+        // 7 at 0.333 is 2.331, the rest stays with the treasury
+        assert_eq!(exchange(7, 0, rate("0.333"), 0), Ok(2));
+        assert_eq!(exchange(700, 2, rate("0.333"), 2), Ok(233));
+        assert_eq!(exchange(3, 0, rate("0.333"), 0), Ok(0));
+        assert_eq!(exchange(1, 0, rate("0.99999999"), 0), Ok(0));
+        assert_eq!(exchange(3, 0, rate("2.5"), 0), Ok(7));
+        assert_eq!(exchange(4, 0, rate("2.5"), 0), Ok(10));
+    }
+
+    #[test]
+    fn exchange_is_exact() {
+        // as a float 100 * 0.29 is 28.999..., which would round down to 28
+        assert_eq!(exchange(100, 0, rate("0.29"), 0), Ok(29));
+        assert_eq!(exchange(i64::MAX, 0, rate("1"), 0), Ok(i64::MAX));
+        assert_eq!(exchange(i64::MAX, 8, rate("1.00000000"), 8), Ok(i64::MAX));
+    }
+
+    #[test]
+    fn exchange_between_decimals() {
+        // 1.23 at 2.5 is 3.075
+        assert_eq!(exchange(123, 2, rate("2.5"), 0), Ok(3));
+        assert_eq!(exchange(123, 2, rate("2.5"), 2), Ok(307));
+        assert_eq!(exchange(123, 2, rate("2.5"), 3), Ok(3075));
+        assert_eq!(exchange(3, 0, rate("2.5"), 8), Ok(750_000_000));
+        // 0.00000009 at 10 is 0.0000009, less than the smallest coin
+        assert_eq!(exchange(9, 8, rate("10"), 0), Ok(0));
+        assert_eq!(exchange(9, 8, rate("10"), 7), Ok(9));
+    }
+
+    #[test]
+    fn exchange_overflow() {
+        assert_eq!(
+            exchange(i64::MAX, 0, rate("2"), 0),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(
+            exchange(i64::MAX, 0, Money::new(i64::MAX, 0), 8),
+            Err(MoneyError::Overflow)
+        );
+    }
+}
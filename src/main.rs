@@ -1,22 +1,43 @@
+mod admin;
 pub mod api;
+mod assets;
+mod catalog;
+mod cli;
+mod config;
+mod csrf;
+mod dashboard;
 mod db_utils;
+mod events;
+mod holds;
+mod invoices;
+mod layout;
+mod ledger;
+mod money;
+mod notifications;
+mod payment_links;
+mod receipts;
+mod registry;
+mod scheduler;
+mod sessions;
+mod shops;
+mod status;
+mod subscriptions;
 pub mod util;
 use auth::AuthUser;
-use db_utils::*;
-use sqlx::SqlitePool;
+use axum_extra::extract::CookieJar;
+use csrf::CsrfField;
+use dashboard::{load_dashboard, Dashboard};
+use events::Events;
+use eyre::WrapErr;
+use layout::{render_page, take_flash, Layout};
+use money::Money;
+use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
 use tower_http::services::ServeDir;
 
-use std::{collections::HashMap, env, sync::Arc};
+use std::{env, str::FromStr, sync::Arc};
 
-use axum::{
-    extract::{ws::WebSocket, State},
-    http::header,
-    response::{Html, Response},
-    routing::{get, post},
-    Router,
-};
-use leptos::{ssr::render_to_string as render, *};
-use tokio::sync::Mutex;
+use axum::{extract::State, middleware, response::Html, routing::get, Router};
+use leptos::*;
 
 use totp_rs::{Secret, TOTP};
 
@@ -26,7 +47,11 @@ pub type DBPool = SqlitePool;
 #[derive(Clone)]
 pub struct App {
     db: Arc<DBPool>,
-    transaction_notif_sockets: Arc<Mutex<HashMap<String, WebSocket>>>,
+    events: Events,
+    /// money new accounts get from the treasury
+    starting_grant: Money,
+    /// where players reach the server, payment links point there
+    public_url: String,
 }
 use util::*;
 
@@ -36,42 +61,71 @@ async fn main() -> eyre::Result<()> {
 }
 async fn run() -> eyre::Result<()> {
     color_eyre::install()?;
-    let pool = SqlitePool::connect(&env::var("DATABASE_URL")?).await?;
+    let args = config::parse_args(env::args().skip(1))?;
+    if args.help {
+        print!("{}", config::USAGE);
+        return Ok(());
+    }
+    let config = config::Config::load(&args)?;
+    if args.print_config {
+        print!("{}", toml::to_string(&config)?);
+    }
+    config.validate(args.command.is_empty())?;
+    if args.print_config {
+        return Ok(());
+    }
+    config::set(config);
+    let config = config::get();
+
+    let options = SqliteConnectOptions::from_str(config.database_url())?.create_if_missing(true);
+    let pool = SqlitePool::connect_with(options).await?;
+    if config.migrate || args.command.first().is_some_and(|c| c == "migrate") {
+        sqlx::migrate!().run(&pool).await.wrap_err(
+            "couldn't apply the migrations, use --no-migrate if the schema is managed elsewhere",
+        )?;
+    }
     let state = App {
         db: Arc::new(pool),
-        transaction_notif_sockets: Arc::new(Mutex::new(HashMap::new())),
+        events: Events::default(),
+        starting_grant: config.economy.starting_grant,
+        public_url: config.public_url.clone(),
     };
+    if !args.command.is_empty() {
+        return cli::run_command(&state, &args.command).await;
+    }
+    tokio::spawn(scheduler::run(state.clone()));
     let app = Router::new()
-        .route(
-            "/css",
-            get(|| async {
-                (
-                    [(header::CONTENT_TYPE, "text/css")],
-                    include_str!(concat!(env!("OUT_DIR"), "/out.css")),
-                )
-            }),
-        )
+        .route("/assets/*file", get(assets::serve_asset))
         .route("/", get(index))
-        .route("/register_form", post(register_form))
-        .route("/login_form", post(login_form))
+        .route("/dashboard", get(dashboard::dashboard_page))
+        .route("/history", get(dashboard::history_page))
+        .route("/settings", get(dashboard::settings_page))
+        .route("/events", get(dashboard::live_updates))
+        .route("/admin", get(admin::admin_page))
+        .route("/shops", get(shops::shops_page))
+        .route("/schmervices", get(registry::schmervices_page))
+        .route("/pay/:code", get(payment_links::payment_link_page))
         .nest("/", auth::get_router())
         .nest("/api", api::get_router())
-        .nest_service("/lua", ServeDir::new("lua"))
+        .nest("/api/admin", admin::get_router())
+        .nest("/api/catalog", catalog::get_router())
+        .nest("/api/shops", shops::get_router())
+        .nest("/api/schmervices", registry::get_router())
+        .nest("/api/holds", holds::get_router())
+        .nest("/api/subscriptions", subscriptions::get_router())
+        .nest("/api/notifications", notifications::get_router())
+        .nest("/api/invoice", invoices::get_router())
+        .nest("/api/pay", payment_links::get_router())
+        .nest_service("/lua", ServeDir::new(&config.static_dir))
+        .layer(middleware::from_fn(csrf::check_csrf))
         .with_state(state);
 
-    // run it with hyper on localhost:3000
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+    // run it with hyper on the configured address
+    let listener = tokio::net::TcpListener::bind(&config.bind).await?;
     axum::serve(listener, app).await.unwrap();
     eyre::Ok(())
 }
 
-async fn register_form() -> Response {
-    render_html(|| view! {<RegisterForm/>})
-}
-async fn login_form() -> Response {
-    render_html(|| view! {<LoginForm/>})
-}
-
 #[component]
 pub fn base_64_image(base64: String, alt: String) -> impl IntoView {
     view! { <img src=format!("data:image/png;base64,{}",base64) alt=alt/>}
@@ -92,12 +146,13 @@ fn get_otp(secret: Secret, username: &str) -> eyre::Result<TOTP> {
 #[component]
 fn login_form() -> impl IntoView {
     view! {
-        <form hx-post="/login" hx-swap="outerHTML">
+        <form hx-post="/login" hx-swap="outerHTML" method="post" action="/login">
             <label>Username: </label>
             <input type="text" name="username"> </input>
             <br/>
             <label>PassCode: </label>
             <input type="number" name="otp"> </input>
+            <CsrfField/>
             <button>Submit</button>
         </form>
 
@@ -107,12 +162,13 @@ fn login_form() -> impl IntoView {
 #[component]
 fn register_form() -> impl IntoView {
     view! {
-        <form hx-post="/register" hx-swap="outerHTML">
+        <form hx-post="/register" hx-swap="outerHTML" method="post" action="/register">
             <label>Display Name:</label>
             <input type="text" name="display_name"> </input>
             <br/>
             <label>Username Name:</label>
             <input type="text" name="username"> </input>
+            <CsrfField/>
             <button>Submit</button>
         </form>
 
@@ -141,46 +197,37 @@ async fn increment_and_get_visits(state: &App) -> Option<i64> {
     data.visits
 }
 
-async fn index(State(state): State<App>, AuthUser(user): AuthUser) -> Html<String> {
+async fn index(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    jar: CookieJar,
+) -> (CookieJar, Html<String>) {
     let visits: i64 = increment_and_get_visits(&state).await.unwrap();
-    let display_name = if let Some((username, _)) = user {
-        Some(
-            get_displayname_from_username(&state, &username)
-                .await
-                .unwrap_or_default(),
-        )
-    } else {
-        None
+    let user = user.map(|(name, _)| name);
+    let dashboard = match (&user, state.db.acquire().await) {
+        (Some(username), Ok(mut conn)) => load_dashboard(&mut conn, username).await.ok(),
+        _ => None,
     };
-    let html = render(move || {
-        let greeting = display_name.map(|name| {
-            move || {
-                view! {cx, <h1>"Hello "{name.clone()}</h1>}
+    let (jar, flash) = take_flash(jar);
+    let html = render_page(move || {
+        let content = match dashboard {
+            Some(data) => view! { <Dashboard data=data/> }.into_view(),
+            None => view! {
+                <h1>Schmervices</h1>
+                <p>"Money, shops and services for the server"</p>
+                <a href="/register" class="button">Signup</a>
+                <a href="/login" class="button">Login</a>
             }
-        });
+            .into_view(),
+        };
 
         view! {
-            <head>
-                <script type="text/javascript" src="https://unpkg.com/htmx.org@1.9.4"></script>
-                <meta charset="UTF-8"></meta>
-                <meta name="viewport" content="width=device-width, initial-scale=1.0"></meta>
-                <link href="/css" rel="stylesheet"></link>
-            </head>
-            <body>
-                {greeting}
-                <button hx-post="/register_form" hx-swap="outerHTML" class="button">
-                    Signup
-                </button>
-                <button hx-post="/login_form" hx-swap="outerHTML" class="button">
-                    Login
-                </button>
-                <button hx-post="/logout" hx-swap="afterend" class="button">
-                    Logout
-                </button>
-            <footer>Visits: {visits} </footer>
-            </body>
+            <Layout title="Home" user=user flash=flash>
+                {content}
+                <p>Visits: {visits}</p>
+            </Layout>
         }
     });
 
-    Html::from("<!DOCTYPE html>\n".to_owned() + &html)
+    (jar, html)
 }
@@ -0,0 +1,262 @@
+use std::{fmt::Display, ops::Neg, str::FromStr};
+
+use serde::{de::Visitor, Deserialize, Serialize};
+
+/// More than this and i64 runs out of room for the whole part real quick
+pub const MAX_DECIMALS: u8 = 8;
+
+/// A fixed point amount of money, stored as a count of the smallest unit of
+/// its currency (`minor`) and how many of the digits are after the point.
+/// `Money::new(250, 2)` is "2.50".
+///
+/// In JSON it's always a string so Lua and JS floats can't mess it up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Money {
+    minor: i64,
+    decimals: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoneyError {
+    Invalid,
+    /// has more decimals than the currency allows
+    TooPrecise,
+    Overflow,
+}
+
+impl Display for MoneyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoneyError::Invalid => write!(f, "Not a valid amount of money"),
+            MoneyError::TooPrecise => write!(f, "Amount has too many decimals for this currency"),
+            MoneyError::Overflow => write!(f, "Amount is too large"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+impl Money {
+    pub fn new(minor: i64, decimals: u8) -> Self {
+        Money { minor, decimals }
+    }
+
+    /// The amount in the smallest unit of the currency
+    pub fn minor(&self) -> i64 {
+        self.minor
+    }
+
+    /// How many digits of `minor` are after the point
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.minor > 0
+    }
+
+    /// Changes how many decimals the amount has, fails instead of rounding
+    pub fn rescale(self, decimals: u8) -> Result<Money, MoneyError> {
+        if decimals > MAX_DECIMALS {
+            return Err(MoneyError::TooPrecise);
+        }
+        let minor = if decimals >= self.decimals {
+            let factor = 10i64.pow((decimals - self.decimals).into());
+            self.minor.checked_mul(factor).ok_or(MoneyError::Overflow)?
+        } else {
+            let factor = 10i64.pow((self.decimals - decimals).into());
+            if self.minor % factor != 0 {
+                return Err(MoneyError::TooPrecise);
+            }
+            self.minor / factor
+        };
+        Ok(Money { minor, decimals })
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Self::Output {
+        Money {
+            minor: -self.minor,
+            decimals: self.decimals,
+        }
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.minor < 0 { "-" } else { "" };
+        let abs = self.minor.unsigned_abs();
+        if self.decimals == 0 {
+            return write!(f, "{sign}{abs}");
+        }
+        let factor = 10u64.pow(self.decimals.into());
+        write!(
+            f,
+            "{sign}{}.{:0width$}",
+            abs / factor,
+            abs % factor,
+            width = self.decimals.into()
+        )
+    }
+}
+
+impl FromStr for Money {
+    type Err = MoneyError;
+
+    /// Parses "12", "-0.5", "2.50" and the like, trailing zeros don't count as decimals
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(MoneyError::Invalid);
+        }
+        let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if !is_digits(whole) || !is_digits(fraction) {
+            return Err(MoneyError::Invalid);
+        }
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > MAX_DECIMALS.into() {
+            return Err(MoneyError::TooPrecise);
+        }
+        let digits = format!("{whole}{fraction}");
+        let minor: i64 = match digits.trim_start_matches('0') {
+            "" => 0,
+            d => d.parse().map_err(|_| MoneyError::Overflow)?,
+        };
+        Ok(Money {
+            minor: if negative { -minor } else { minor },
+            decimals: fraction.len() as u8,
+        })
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct MoneyVisitor;
+
+impl<'de> Visitor<'de> for MoneyVisitor {
+    type Value = Money;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an amount of money like \"2.50\"")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    // plain numbers are still accepted so older clients keep working
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Money::new(v, 0))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        let v = i64::try_from(v).map_err(|_| E::custom(MoneyError::Overflow))?;
+        Ok(Money::new(v, 0))
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        v.to_string().parse().map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Money, MoneyError> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_decimals() {
+        assert_eq!(parse("2.5"), Ok(Money::new(25, 1)));
+        assert_eq!(parse("-0.10"), Ok(Money::new(-1, 1)));
+        assert_eq!(parse("+1"), Ok(Money::new(1, 0)));
+        assert_eq!(parse(" 12 "), Ok(Money::new(12, 0)));
+        assert_eq!(parse(".5"), Ok(Money::new(5, 1)));
+        assert_eq!(parse("3."), Ok(Money::new(3, 0)));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for s in [
+            ".", "", "-", "+", "1.2.3", "1e5", "--1", "1,5", "0x10", " . ",
+        ] {
+            assert_eq!(parse(s), Err(MoneyError::Invalid), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn limits_decimals() {
+        assert_eq!(parse("0.12345678"), Ok(Money::new(12345678, 8)));
+        assert_eq!(parse("0.123456789"), Err(MoneyError::TooPrecise));
+        // trailing zeros don't count
+        assert_eq!(parse("1.500000000000"), Ok(Money::new(15, 1)));
+    }
+
+    #[test]
+    fn overflows() {
+        assert_eq!(parse("9223372036854775807"), Ok(Money::new(i64::MAX, 0)));
+        assert_eq!(parse("9223372036854775808"), Err(MoneyError::Overflow));
+        assert_eq!(parse("92233720368547758.08"), Err(MoneyError::Overflow));
+        assert_eq!(parse("-99999999999999999999"), Err(MoneyError::Overflow));
+    }
+
+    #[test]
+    fn displays_with_all_decimals() {
+        assert_eq!(Money::new(250, 2).to_string(), "2.50");
+        assert_eq!(Money::new(-5, 2).to_string(), "-0.05");
+        assert_eq!(Money::new(7, 0).to_string(), "7");
+        assert_eq!(Money::new(i64::MIN, 0).to_string(), i64::MIN.to_string());
+    }
+
+    #[test]
+    fn rescales_without_rounding() {
+        assert_eq!(Money::new(25, 1).rescale(3), Ok(Money::new(2500, 3)));
+        assert_eq!(Money::new(2500, 3).rescale(1), Ok(Money::new(25, 1)));
+        assert_eq!(Money::new(25, 1).rescale(0), Err(MoneyError::TooPrecise));
+        assert_eq!(Money::new(-1, 2).rescale(1), Err(MoneyError::TooPrecise));
+        assert_eq!(Money::new(1, 0).rescale(9), Err(MoneyError::TooPrecise));
+        assert_eq!(
+            Money::new(i64::MAX, 0).rescale(1),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn json() {
+        let from_json = |s: &str| serde_json::from_str::<Money>(s);
+        assert_eq!(from_json(r#""2.50""#).unwrap(), Money::new(25, 1));
+        assert_eq!(from_json(r#""-3""#).unwrap(), Money::new(-3, 0));
+        assert_eq!(from_json("2.5").unwrap(), Money::new(25, 1));
+        assert_eq!(from_json("0.1").unwrap(), Money::new(1, 1));
+        assert_eq!(from_json("-4").unwrap(), Money::new(-4, 0));
+        assert_eq!(from_json("18").unwrap(), Money::new(18, 0));
+        assert!(from_json(r#""abc""#).is_err());
+        assert!(from_json("18446744073709551615").is_err());
+        assert!(from_json("0.123456789").is_err());
+        assert!(from_json("null").is_err());
+        assert_eq!(
+            serde_json::to_string(&Money::new(250, 2)).unwrap(),
+            r#""2.50""#
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+use crate::{auth::AuthUser, App};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/list_notifications", post(list_notifications))
+        .route("/mark_read", post(mark_read))
+}
+
+/// Something that happened to the user while they weren't looking, like a failed charge
+#[derive(Serialize, Debug)]
+pub struct Notification {
+    pub id: i64,
+    pub message: String,
+    pub timestamp: i64,
+    pub read: bool,
+}
+
+pub(crate) async fn notify_user(
+    conn: &mut SqliteConnection,
+    username: &str,
+    message: &str,
+) -> sqlx::Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO notifications (username, message, timestamp) VALUES (?,?,?);",
+        username,
+        message,
+        now
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_notifications(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> sqlx::Result<Vec<Notification>> {
+    sqlx::query_as!(
+        Notification,
+        "SELECT id, message, timestamp, read FROM notifications
+         WHERE username = ? ORDER BY id DESC LIMIT 50;",
+        username
+    )
+    .fetch_all(conn)
+    .await
+}
+
+async fn list_notifications(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<Notification>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let notifications = get_notifications(&mut conn, &user)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(notifications))
+}
+
+async fn mark_read(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    sqlx::query!(
+        "UPDATE notifications SET read = 1 WHERE username = ?;",
+        user
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
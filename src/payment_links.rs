@@ -0,0 +1,376 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::{AuthUser, RequestType},
+    db_utils::is_frozen,
+    layout::{render_page, Layout},
+    ledger::{default_currency, record_transfer, to_minor_units},
+    money::Money,
+    notifications::notify_user,
+    status::PaymentLinkStatus,
+    util::{get_random_string, render_html, ApiRequest, RequestTypeEnum},
+    App, Base64Image,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/create_payment_link", post(create_payment_link))
+        .route("/cancel_payment_link/:code", post(cancel_payment_link))
+        .route("/list_payment_links", post(list_payment_links))
+        .route("/pay/:code", post(pay))
+        .route("/:code", get(get_payment_link))
+}
+
+#[derive(Deserialize, Debug)]
+struct CreatePaymentLink {
+    amount: Money,
+    #[serde(default = "default_currency")]
+    currency: String,
+    /// what it's for, shown to whoever opens the link
+    memo: String,
+}
+
+#[derive(Serialize, Debug)]
+struct CreatedPaymentLink {
+    code: String,
+    url: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PaymentLink {
+    pub code: String,
+    pub seller: String,
+    pub amount: Money,
+    pub currency: String,
+    pub memo: String,
+    pub status: PaymentLinkStatus,
+    pub created: i64,
+    /// only shown to the seller
+    pub paid_by: Option<String>,
+    pub transaction_id: Option<String>,
+}
+
+/// Where the link can be opened. Not taken from the Host header,
+/// a request could point the QR anywhere with that
+fn link_url(public_url: &str, code: &str) -> String {
+    format!("{}/pay/{code}", public_url.trim_end_matches('/'))
+}
+
+async fn fetch_payment_links(
+    conn: &mut SqliteConnection,
+    code: Option<&str>,
+    seller: Option<&str>,
+) -> sqlx::Result<Vec<PaymentLink>> {
+    let rows = sqlx::query!(
+        r#"SELECT payment_links.code, seller, amount, currency, decimals, memo,
+            status as "status: PaymentLinkStatus", created, paid_by, transaction_id
+           FROM payment_links JOIN currencies ON currencies.code = payment_links.currency
+           WHERE (?1 IS NULL OR payment_links.code = ?1) AND (?2 IS NULL OR seller = ?2)
+           ORDER BY created DESC LIMIT 50;"#,
+        code,
+        seller
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| PaymentLink {
+            code: r.code,
+            seller: r.seller,
+            amount: Money::new(r.amount, r.decimals as u8),
+            currency: r.currency,
+            memo: r.memo,
+            status: r.status,
+            created: r.created,
+            paid_by: r.paid_by,
+            transaction_id: r.transaction_id,
+        })
+        .collect())
+}
+
+/// The link with the payer hidden from everyone but the seller
+async fn fetch_payment_link(
+    conn: &mut SqliteConnection,
+    code: &str,
+    user: Option<&str>,
+) -> Result<PaymentLink, StatusCode> {
+    let mut link = fetch_payment_links(conn, Some(code), None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .pop()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if user != Some(link.seller.as_str()) {
+        link.paid_by = None;
+        link.transaction_id = None;
+    }
+    Ok(link)
+}
+
+/// Seller makes a single use link anyone can pay, returns its code and url
+async fn create_payment_link(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<CreatePaymentLink>,
+) -> Result<Json<CreatedPaymentLink>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    if !data.amount.is_positive() {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    match is_frozen(&state, &user).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let amount = to_minor_units(&mut conn, &data.currency, data.amount).await?;
+    let code = get_random_string(6);
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO payment_links (code, seller, amount, currency, memo, status, created)
+         VALUES (?,?,?,?,?,?,?);",
+        code,
+        user,
+        amount,
+        data.currency,
+        data.memo,
+        PaymentLinkStatus::Open,
+        now
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let url = link_url(&state.public_url, &code);
+    Ok(Json(CreatedPaymentLink { code, url }))
+}
+
+async fn cancel_payment_link(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(code): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let r = sqlx::query!(
+        "UPDATE payment_links SET status = ? WHERE code = ? AND seller = ? AND status = ?;",
+        PaymentLinkStatus::Cancelled,
+        code,
+        user,
+        PaymentLinkStatus::Open
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::CONFLICT)?;
+    }
+    Ok(StatusCode::OK)
+}
+
+/// The links the logged in user made, newest first
+async fn list_payment_links(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<PaymentLink>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let links = fetch_payment_links(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        None,
+        Some(&user),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(links))
+}
+
+async fn get_payment_link(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(code): Path<String>,
+) -> Result<Json<PaymentLink>, StatusCode> {
+    let user = user.map(|(name, _)| name);
+    let link = fetch_payment_link(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        &code,
+        user.as_deref(),
+    )
+    .await?;
+    Ok(Json(link))
+}
+
+/// Moves the money and closes the link, returns the id of the transaction
+async fn pay_link(state: &App, user: &str, code: &str) -> Result<String, StatusCode> {
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let link = sqlx::query!(
+        r#"SELECT seller, amount, currency, memo, status as "status: PaymentLinkStatus"
+           FROM payment_links WHERE code = ?;"#,
+        code
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    if !link.status.can_become(PaymentLinkStatus::Paid) {
+        Err(StatusCode::GONE)?;
+    }
+    if link.seller == user {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    for name in [user, link.seller.as_str()] {
+        if !matches!(is_frozen(state, name).await, Ok(false)) {
+            Err(StatusCode::FORBIDDEN)?;
+        }
+    }
+    let id = record_transfer(
+        &mut tx,
+        user,
+        &link.seller,
+        &link.memo,
+        &link.currency,
+        link.amount,
+    )
+    .await?;
+    let r = sqlx::query!(
+        "UPDATE payment_links SET status = ?, paid_by = ?, transaction_id = ?
+         WHERE code = ? AND status = ?;",
+        PaymentLinkStatus::Paid,
+        user,
+        id,
+        code,
+        PaymentLinkStatus::Open
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // somebody else was faster
+    if r.rows_affected() == 0 {
+        Err(StatusCode::GONE)?;
+    }
+    let msg = format!("{user} paid your link for {}", link.memo);
+    notify_user(&mut tx, &link.seller, &msg)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([user, &link.seller]);
+    Ok(id)
+}
+
+async fn pay(
+    State(state): State<App>,
+    RequestType(req_type): RequestType,
+    AuthUser(user): AuthUser,
+    Path(code): Path<String>,
+) -> Result<Response, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let result = pay_link(&state, &user, &code).await;
+    Ok(match req_type {
+        RequestTypeEnum::Json => Json(result?).into_response(),
+        RequestTypeEnum::Html => {
+            let msg = match result {
+                Ok(_) => "Paid",
+                Err(StatusCode::PAYMENT_REQUIRED) => "You don't have enough money for this",
+                Err(StatusCode::GONE) => "This link was already paid or cancelled",
+                Err(StatusCode::FORBIDDEN) => "One of the accounts is frozen",
+                Err(StatusCode::BAD_REQUEST) => "You can't pay your own link",
+                Err(_) => "Something went wrong",
+            };
+            render_html(move || view! { <p>{msg}</p> })
+        }
+    })
+}
+
+/// What a payment link opens to, a QR to show the other player and a button to pay
+pub async fn payment_link_page(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(code): Path<String>,
+) -> Result<Html<String>, StatusCode> {
+    let user = user.map(|(name, _)| name);
+    let link = fetch_payment_link(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        &code,
+        user.as_deref(),
+    )
+    .await?;
+    let url = link_url(&state.public_url, &code);
+    let qr_code = totp_rs::qrcodegen_image::draw_base64(&url)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let html = render_page(move || {
+        let viewer = user.clone();
+        let action = match (link.status, user) {
+            (PaymentLinkStatus::Open, Some(user)) if user == link.seller => {
+                view! { <p>"Show this to whoever should pay"</p> }.into_view()
+            }
+            (PaymentLinkStatus::Open, Some(_)) => view! {
+                <button hx-post=format!("/api/pay/pay/{code}") hx-swap="outerHTML" class="button">
+                    Pay
+                </button>
+            }
+            .into_view(),
+            (PaymentLinkStatus::Open, None) => {
+                view! { <p><a href="/">Log in</a>" to pay"</p> }.into_view()
+            }
+            (status, _) => view! { <p>"This link is "{status.as_str()}</p> }.into_view(),
+        };
+
+        view! {
+            <Layout title="Pay" user=viewer>
+                <h1>{link.amount.to_string()}" "{link.currency}</h1>
+                <p>{link.memo}</p>
+                <p>"to "{link.seller}</p>
+                <Base64Image base64=qr_code alt=url/>
+                {action}
+            </Layout>
+        }
+    });
+
+    Ok(html)
+}
@@ -0,0 +1,375 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::AuthUser,
+    invoices::{fetch_invoice, Invoice, LineKind},
+    money::Money,
+    status::TransactionStatus,
+    util::{format_timestamp, get_random_string, ApiRequest},
+    App,
+};
+
+/// Size of a CC:Tweaked printer page
+pub const PAGE_WIDTH: usize = 25;
+pub const PAGE_HEIGHT: usize = 21;
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ReceiptRequest {
+    /// which of the sellers shops to put on top, their first one if not given
+    shop: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Receipt {
+    pub shop: String,
+    pub code: String,
+    #[serde(flatten)]
+    pub invoice: Invoice,
+    /// the receipt laid out for the printer, at most 21 lines of at most 25 characters each
+    pub pages: Vec<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct VerifyReceipt {
+    id: String,
+    code: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReceiptCheck {
+    pub valid: bool,
+    pub seller: Option<String>,
+    pub buyer: Option<String>,
+    pub amount: Option<Money>,
+    pub currency: Option<String>,
+    pub status: Option<TransactionStatus>,
+    pub timestamp: Option<i64>,
+}
+
+/// Printers only know ascii
+fn printable(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect()
+}
+
+/// Splits text into lines that fit the page, breaking words that don't fit on their own
+fn wrap(s: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in printable(s).split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > PAGE_WIDTH {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+        while line.len() > PAGE_WIDTH {
+            let rest = line.split_off(PAGE_WIDTH);
+            lines.push(std::mem::replace(&mut line, rest));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// `left` and `right` on one line with the gap between them. If they don't fit `right`
+/// goes on the next line, broken up rather than cut since it's usually an amount
+fn row(left: &str, right: &str) -> Vec<String> {
+    let left: String = printable(left).chars().take(PAGE_WIDTH).collect();
+    let right = printable(right);
+    let gap = if left.is_empty() { 0 } else { 1 };
+    if left.len() + gap + right.len() <= PAGE_WIDTH {
+        let width = PAGE_WIDTH - right.len();
+        return vec![format!("{left:<width$}{right}")];
+    }
+    let mut lines = Vec::new();
+    if !left.is_empty() {
+        lines.push(left);
+    }
+    let right: Vec<char> = right.chars().collect();
+    for part in right.chunks(PAGE_WIDTH) {
+        let part: String = part.iter().collect();
+        lines.push(format!("{part:>PAGE_WIDTH$}"));
+    }
+    lines
+}
+
+fn center(s: &str) -> String {
+    let s: String = printable(s).chars().take(PAGE_WIDTH).collect();
+    format!("{s:^PAGE_WIDTH$}").trim_end().to_owned()
+}
+
+/// Lays the receipt out as printer pages
+fn layout(shop: &str, code: &str, invoice: &Invoice) -> Vec<Vec<String>> {
+    let rule = "-".repeat(PAGE_WIDTH);
+    let mut lines = vec![center(shop), rule.clone()];
+    lines.push(format_timestamp(invoice.timestamp));
+    lines.extend(row("Seller:", &invoice.seller));
+    lines.extend(row("Buyer:", &invoice.buyer));
+    lines.push(rule.clone());
+    for line in invoice.lines.iter().filter(|l| l.kind == LineKind::Item) {
+        lines.extend(wrap(&line.description));
+        let detail = match (line.quantity, line.unit_price) {
+            (Some(q), Some(p)) if q != 1 => format!(" {q} x {p}"),
+            _ => String::new(),
+        };
+        lines.extend(row(&detail, &line.amount.to_string()));
+    }
+    lines.push(rule.clone());
+    if invoice.lines.iter().any(|l| l.kind != LineKind::Item) {
+        lines.extend(row("Subtotal", &invoice.subtotal.to_string()));
+        for line in invoice.lines.iter().filter(|l| l.kind != LineKind::Item) {
+            let label = match line.percent {
+                Some(p) => format!("{} {p}%", line.description),
+                None => line.description.clone(),
+            };
+            lines.extend(row(&label, &line.amount.to_string()));
+        }
+    }
+    lines.extend(row(
+        "TOTAL",
+        &format!("{} {}", invoice.total, invoice.currency),
+    ));
+    if invoice.status == TransactionStatus::Refunded {
+        lines.push(center("REFUNDED"));
+    }
+    lines.push(rule);
+    lines.extend(row("Id:", &invoice.id));
+    lines.extend(row("Code:", code));
+    lines
+        .chunks(PAGE_HEIGHT)
+        .map(|page| page.to_vec())
+        .collect()
+}
+
+/// The code of the transaction, made up the first time it's asked for
+async fn get_receipt_code(conn: &mut SqliteConnection, id: &str) -> sqlx::Result<String> {
+    let code = get_random_string(8);
+    sqlx::query!(
+        "UPDATE transactions SET receipt_code = ? WHERE id = ? AND receipt_code IS NULL;",
+        code,
+        id
+    )
+    .execute(&mut *conn)
+    .await?;
+    let r = sqlx::query!("SELECT receipt_code FROM transactions WHERE id = ?;", id)
+        .fetch_one(conn)
+        .await?;
+    Ok(r.receipt_code.unwrap_or(code))
+}
+
+/// Receipt of a paid transaction for its seller or buyer, ready for a printer
+pub(crate) async fn get_receipt(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+    ApiRequest(data): ApiRequest<ReceiptRequest>,
+) -> Result<Json<Receipt>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let invoice = fetch_invoice(&mut conn, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if invoice.buyer != user && invoice.seller != user {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    // nothing was paid, so there is nothing to give a receipt for
+    if !matches!(
+        invoice.status,
+        TransactionStatus::Accepted | TransactionStatus::Refunded
+    ) {
+        Err(StatusCode::CONFLICT)?;
+    }
+    let shop = sqlx::query!(
+        "SELECT name FROM shops WHERE owner = ?1 AND (?2 IS NULL OR id = ?2)
+         ORDER BY created LIMIT 1;",
+        invoice.seller,
+        data.shop
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let shop = match shop {
+        Some(shop) => shop.name,
+        None => invoice.seller.clone(),
+    };
+    let code = get_receipt_code(&mut conn, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let pages = layout(&shop, &code, &invoice);
+    Ok(Json(Receipt {
+        shop,
+        code,
+        invoice,
+        pages,
+    }))
+}
+
+/// Anyone holding a receipt can check it's real, and whether it got refunded since
+pub(crate) async fn verify_receipt(
+    State(state): State<App>,
+    ApiRequest(data): ApiRequest<VerifyReceipt>,
+) -> Result<Json<ReceiptCheck>, StatusCode> {
+    let r = sqlx::query!(
+        r#"SELECT buyer, seller, amount, currency, decimals,
+            status as "status: TransactionStatus", timestamp
+           FROM transactions JOIN currencies ON currencies.code = transactions.currency
+           WHERE id = ? AND receipt_code = ?;"#,
+        data.id,
+        data.code
+    )
+    .fetch_optional(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(match r {
+        Some(r) => ReceiptCheck {
+            valid: true,
+            seller: Some(r.seller),
+            buyer: Some(r.buyer),
+            amount: Some(Money::new(r.amount, r.decimals as u8)),
+            currency: Some(r.currency),
+            status: Some(r.status),
+            timestamp: Some(r.timestamp),
+        },
+        None => ReceiptCheck {
+            valid: false,
+            seller: None,
+            buyer: None,
+            amount: None,
+            currency: None,
+            status: None,
+            timestamp: None,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoices::InvoiceLine;
+
+    fn assert_fits(lines: &[String]) {
+        for line in lines {
+            assert!(line.len() <= PAGE_WIDTH, "too wide: {line:?}");
+        }
+    }
+
+    fn item(description: &str, quantity: i64, unit_price: Money, amount: Money) -> InvoiceLine {
+        InvoiceLine {
+            kind: LineKind::Item,
+            description: description.to_owned(),
+            quantity: Some(quantity),
+            unit_price: Some(unit_price),
+            percent: None,
+            amount,
+        }
+    }
+
+    fn invoice(lines: Vec<InvoiceLine>, total: Money, currency: &str) -> Invoice {
+        Invoice {
+            id: "AbCdEfGh".to_owned(),
+            buyer: "a_buyer_with_a_really_long_username".to_owned(),
+            seller: "seller".to_owned(),
+            name: "Order".to_owned(),
+            status: TransactionStatus::Refunded,
+            timestamp: 1_700_000_000,
+            currency: currency.to_owned(),
+            lines,
+            subtotal: total,
+            total,
+        }
+    }
+
+    #[test]
+    fn wrap_breaks_words_and_long_words() {
+        let lines =
+            wrap("a few short words and one aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+        assert_fits(&lines);
+        assert_eq!(lines[0], "a few short words and one");
+        assert_eq!(lines[1], "a".repeat(PAGE_WIDTH));
+        assert_eq!(wrap("caf\u{e9}"), ["caf?"]);
+        assert!(wrap("   ").is_empty());
+    }
+
+    #[test]
+    fn row_fits_on_one_line() {
+        assert_eq!(row("Total", "5 COIN"), ["Total              5 COIN"]);
+        assert_eq!(row("", &"9".repeat(PAGE_WIDTH)), ["9".repeat(PAGE_WIDTH)]);
+    }
+
+    #[test]
+    fn row_moves_long_right_side_down() {
+        let lines = row("TOTAL", "9223372036854775807 COIN");
+        assert_fits(&lines);
+        assert_eq!(lines, ["TOTAL", " 9223372036854775807 COIN"]);
+
+        let long = "1234567890".repeat(4);
+        let lines = row("Seller:", &long);
+        assert_fits(&lines);
+        assert_eq!(lines.concat().replace(' ', ""), format!("Seller:{long}"));
+
+        assert_fits(&row(&"label ".repeat(10), "1.5"));
+    }
+
+    #[test]
+    fn layout_fits_the_printer() {
+        let huge = Money::new(i64::MAX, 8);
+        let mut lines: Vec<_> = (0..30)
+            .map(|i| item(&format!("Item number {i} with a long name"), 3, huge, huge))
+            .collect();
+        lines.push(InvoiceLine {
+            kind: LineKind::Fee,
+            description: "A fee with a description that is far too long".to_owned(),
+            quantity: None,
+            unit_price: None,
+            percent: None,
+            amount: huge,
+        });
+        let pages = layout(
+            "A shop name that goes on and on",
+            "CODE1234",
+            &invoice(lines, huge, "LONGCODE"),
+        );
+        assert!(pages.len() > 1);
+        for page in &pages {
+            assert!(page.len() <= PAGE_HEIGHT, "{} lines", page.len());
+            assert_fits(page);
+        }
+        let all = pages.concat();
+        assert!(all.iter().any(|l| l.contains("REFUNDED")));
+        // the total is too long for its line and gets broken up, but all of it is there
+        let at = all.iter().position(|l| l == "TOTAL").unwrap();
+        let total: String = all[at + 1..at + 3].iter().map(|l| l.trim_start()).collect();
+        assert_eq!(total, "92233720368.54775807 LONGCODE");
+    }
+}
@@ -0,0 +1,610 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+    routing::{get, post},
+    Json, Router,
+};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::AuthUser,
+    db_utils::is_frozen,
+    layout::{render_page, Layout},
+    ledger::{default_currency, to_minor_units},
+    money::Money,
+    sessions::{close_session, list_sessions, open_session, DEFAULT_MAX_MINUTES, MAX_MINUTES},
+    util::{format_timestamp, ApiRequest},
+    App,
+};
+
+/// A schmervice counts as offline if its computer didn't send a heartbeat for this many seconds
+pub const HEARTBEAT_TIMEOUT: i64 = 120;
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/register_schmervice", post(register_schmervice))
+        .route("/remove_schmervice", post(remove_schmervice))
+        .route("/set_enabled", post(set_enabled))
+        .route("/claim", post(claim))
+        .route("/release", post(release))
+        .route("/heartbeat", post(heartbeat))
+        .route("/list_schmervices", post(list_schmervices))
+        .route("/history", get(history))
+        .route("/sessions", post(list_sessions))
+}
+
+/// Same shape as the `schmervice` in schmervice_lib.lua, plus what the server knows
+#[derive(Serialize, Debug)]
+pub struct Schmervice {
+    pub owner: String,
+    pub name: String,
+    pub enabled: bool,
+    pub in_use: bool,
+    /// who is using it, "" when nobody is like in the lua version
+    pub username: String,
+    pub claimed_at: Option<i64>,
+    pub online: bool,
+    /// per started minute, 0 for free schmervices
+    pub rate: Money,
+    pub currency: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SchmerviceEvent {
+    pub owner: String,
+    pub name: String,
+    pub username: String,
+    pub action: String,
+    pub timestamp: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct RegisterSchmervice {
+    name: String,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    /// per started minute
+    rate: Option<Money>,
+    #[serde(default = "default_currency")]
+    currency: String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+struct SchmerviceName {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SetEnabled {
+    name: String,
+    enabled: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct SchmerviceRef {
+    /// defaults to the logged in user, for the computer running the schmervice
+    owner: Option<String>,
+    name: String,
+    /// for paid schmervices, how many minutes the hold should cover
+    max_minutes: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListSchmervices {
+    owner: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct HistoryFilter {
+    owner: Option<String>,
+    name: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+struct StoredSchmervice {
+    owner: String,
+    name: String,
+    enabled: bool,
+    in_use_by: Option<String>,
+    claimed_at: Option<i64>,
+    last_heartbeat: i64,
+    rate: i64,
+    currency: String,
+    decimals: i64,
+}
+
+impl From<StoredSchmervice> for Schmervice {
+    fn from(s: StoredSchmervice) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        Schmervice {
+            owner: s.owner,
+            name: s.name,
+            enabled: s.enabled,
+            in_use: s.in_use_by.is_some(),
+            username: s.in_use_by.unwrap_or_default(),
+            claimed_at: s.claimed_at,
+            online: now - s.last_heartbeat <= HEARTBEAT_TIMEOUT,
+            rate: Money::new(s.rate, s.decimals as u8),
+            currency: s.currency,
+        }
+    }
+}
+
+pub(crate) async fn log_event(
+    conn: &mut SqliteConnection,
+    owner: &str,
+    name: &str,
+    username: &str,
+    action: &str,
+) -> sqlx::Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO schmervice_events (owner, name, username, action, timestamp)
+         VALUES (?,?,?,?,?);",
+        owner,
+        name,
+        username,
+        action,
+        now
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn fetch_schmervice(
+    conn: &mut SqliteConnection,
+    owner: &str,
+    name: &str,
+) -> sqlx::Result<Option<StoredSchmervice>> {
+    sqlx::query_as!(
+        StoredSchmervice,
+        "SELECT owner, schmervices.name, enabled, in_use_by, claimed_at, last_heartbeat, rate,
+            schmervices.currency, currencies.decimals
+         FROM schmervices JOIN currencies ON currencies.code = schmervices.currency
+         WHERE owner = ? AND schmervices.name = ?;",
+        owner,
+        name
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Every schmervice of `owner`, or all of them
+pub async fn get_schmervices(
+    conn: &mut SqliteConnection,
+    owner: Option<&str>,
+) -> sqlx::Result<Vec<Schmervice>> {
+    let rows = sqlx::query_as!(
+        StoredSchmervice,
+        "SELECT owner, schmervices.name, enabled, in_use_by, claimed_at, last_heartbeat, rate,
+            schmervices.currency, currencies.decimals
+         FROM schmervices JOIN currencies ON currencies.code = schmervices.currency
+         WHERE ?1 IS NULL OR owner = ?1 ORDER BY owner, schmervices.name;",
+        owner
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows.into_iter().map(Schmervice::from).collect())
+}
+
+pub async fn get_schmervice_events(
+    conn: &mut SqliteConnection,
+    owner: Option<&str>,
+    name: Option<&str>,
+    limit: i64,
+) -> sqlx::Result<Vec<SchmerviceEvent>> {
+    sqlx::query_as!(
+        SchmerviceEvent,
+        "SELECT owner, name, username, action, timestamp FROM schmervice_events
+         WHERE (?1 IS NULL OR owner = ?1) AND (?2 IS NULL OR name = ?2)
+         ORDER BY id DESC LIMIT ?3;",
+        owner,
+        name,
+        limit
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// Adds a schmervice for the logged in user, re-registering keeps who is using it
+async fn register_schmervice(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<RegisterSchmervice>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let name = data.name.trim();
+    if name.is_empty() || name.len() > 32 {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    match is_frozen(&state, &user).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let rate = match data.rate {
+        Some(rate) if rate.minor() < 0 => Err(StatusCode::BAD_REQUEST)?,
+        Some(rate) => to_minor_units(&mut tx, &data.currency, rate).await?,
+        None => 0,
+    };
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO schmervices (owner, name, enabled, last_heartbeat, rate, currency)
+         VALUES (?,?,?,?,?,?)
+         ON CONFLICT (owner, name) DO UPDATE SET
+            enabled = excluded.enabled,
+            last_heartbeat = excluded.last_heartbeat,
+            rate = excluded.rate,
+            currency = excluded.currency;",
+        user,
+        name,
+        data.enabled,
+        now,
+        rate,
+        data.currency
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    log_event(&mut tx, &user, name, &user, "register")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
+
+async fn remove_schmervice(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<SchmerviceName>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    close_session(&mut tx, &user, &data.name).await?;
+    let r = sqlx::query!(
+        "DELETE FROM schmervices WHERE owner = ? AND name = ?;",
+        user,
+        data.name
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    log_event(&mut tx, &user, &data.name, &user, "remove")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
+
+async fn set_enabled(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<SetEnabled>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let r = sqlx::query!(
+        "UPDATE schmervices SET enabled = ? WHERE owner = ? AND name = ?;",
+        data.enabled,
+        user,
+        data.name
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    let action = if data.enabled { "enable" } else { "disable" };
+    log_event(&mut tx, &user, &data.name, &user, action)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
+
+/// Starts using a schmervice, 409 if someone else is using it or it's disabled.
+/// Only ever for the logged in user, the computer running it learns about the claim
+/// from its heartbeat. It can't claim for whoever asked over rednet, that isn't authenticated.
+/// Paid schmervices put a hold on the users money.
+async fn claim(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<SchmerviceRef>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let owner = data.owner.unwrap_or_else(|| user.clone());
+    match is_frozen(&state, &user).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let schmervice = fetch_schmervice(&mut tx, &owner, &data.name)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    match schmervice.in_use_by {
+        Some(by) if by == user => return Ok(StatusCode::OK),
+        Some(_) => Err(StatusCode::CONFLICT)?,
+        None if !schmervice.enabled => Err(StatusCode::CONFLICT)?,
+        None => {}
+    }
+    if schmervice.rate > 0 {
+        let max_minutes = data.max_minutes.unwrap_or(DEFAULT_MAX_MINUTES);
+        if !(1..=MAX_MINUTES).contains(&max_minutes) {
+            Err(StatusCode::BAD_REQUEST)?;
+        }
+        open_session(
+            &mut tx,
+            &owner,
+            &data.name,
+            &user,
+            schmervice.rate,
+            &schmervice.currency,
+            max_minutes,
+        )
+        .await?;
+    }
+    let now = chrono::Utc::now().timestamp();
+    let claimed = sqlx::query!(
+        "UPDATE schmervices SET in_use_by = ?, claimed_at = ?
+         WHERE owner = ? AND name = ? AND in_use_by IS NULL;",
+        user,
+        now,
+        owner,
+        data.name
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // someone else claimed it since it was read, dropping tx undoes the session
+    if claimed.rows_affected() == 0 {
+        Err(StatusCode::CONFLICT)?;
+    }
+    log_event(&mut tx, &owner, &data.name, &user, "claim")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([&user, &owner]);
+    Ok(StatusCode::OK)
+}
+
+/// Stops using a schmervice and bills it if it's paid, the owner can release it for anyone
+async fn release(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<SchmerviceRef>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let owner = data.owner.unwrap_or_else(|| user.clone());
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let schmervice = fetch_schmervice(&mut tx, &owner, &data.name)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let Some(in_use_by) = schmervice.in_use_by else {
+        return Ok(StatusCode::OK);
+    };
+    if in_use_by != user && schmervice.owner != user {
+        Err(StatusCode::FORBIDDEN)?;
+    }
+    close_session(&mut tx, &owner, &data.name).await?;
+    sqlx::query!(
+        "UPDATE schmervices SET in_use_by = NULL, claimed_at = NULL WHERE owner = ? AND name = ?;",
+        owner,
+        data.name
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    log_event(&mut tx, &owner, &data.name, &in_use_by, "release")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([&in_use_by, &owner]);
+    Ok(StatusCode::OK)
+}
+
+/// Marks all schmervices of the logged in user as online and returns them,
+/// so a rebooted computer can pick up where it left off
+async fn heartbeat(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<Schmervice>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "UPDATE schmervices SET last_heartbeat = ? WHERE owner = ?;",
+        now,
+        user
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let schmervices = get_schmervices(&mut conn, Some(&user))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(schmervices))
+}
+
+async fn list_schmervices(
+    State(state): State<App>,
+    ApiRequest(data): ApiRequest<ListSchmervices>,
+) -> Result<Json<Vec<Schmervice>>, StatusCode> {
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let schmervices = get_schmervices(&mut conn, Some(&data.owner))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(schmervices))
+}
+
+async fn history(
+    State(state): State<App>,
+    Query(filter): Query<HistoryFilter>,
+) -> Result<Json<Vec<SchmerviceEvent>>, StatusCode> {
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let events = get_schmervice_events(
+        &mut conn,
+        filter.owner.as_deref(),
+        filter.name.as_deref(),
+        filter.limit,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(events))
+}
+
+/// Who is using what right now, and what happened recently
+pub async fn schmervices_page(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Query(filter): Query<HistoryFilter>,
+) -> Result<Html<String>, StatusCode> {
+    let user = user.map(|(name, _)| name);
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let owner = filter.owner.as_deref().filter(|o| !o.is_empty());
+    let schmervices = get_schmervices(&mut conn, owner)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let events = get_schmervice_events(&mut conn, owner, None, filter.limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let html = render_page(move || {
+        let schmervices = schmervices
+            .into_iter()
+            .map(|s| {
+                view! {
+                    <tr>
+                        <td>{s.owner}</td>
+                        <td>{s.name}</td>
+                        <td class:text-red-600=!s.online>
+                            {if s.online { "Online" } else { "Offline" }}
+                        </td>
+                        <td>{if s.enabled { "Enabled" } else { "Disabled" }}</td>
+                        <td>{s.rate.to_string()}" "{s.currency}" / min"</td>
+                        <td>{s.username}</td>
+                        <td>{s.claimed_at.map(format_timestamp)}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+        let events = events
+            .into_iter()
+            .map(|e| {
+                view! {
+                    <tr>
+                        <td>{format_timestamp(e.timestamp)}</td>
+                        <td>{e.owner}</td>
+                        <td>{e.name}</td>
+                        <td>{e.username}</td>
+                        <td>{e.action}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        view! {
+            <Layout title="Schmervices" user=user>
+                <h1>Schmervices</h1>
+                <table>
+                    <tr>
+                        <th>Owner</th><th>Name</th><th></th><th></th><th>Price</th>
+                        <th>Used By</th><th>Since</th>
+                    </tr>
+                    {schmervices}
+                </table>
+                <h2>History</h2>
+                <table>
+                    <tr><th>Time</th><th>Owner</th><th>Name</th><th>User</th><th>Action</th></tr>
+                    {events}
+                </table>
+            </Layout>
+        }
+    });
+
+    Ok(html)
+}
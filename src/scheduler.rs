@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use crate::{api, holds, sessions, subscriptions, App};
+
+/// How often the jobs run
+pub const TICK: Duration = Duration::from_secs(60);
+
+/// Runs everything that has to happen without a request, like charging subscriptions.
+/// The jobs run one after another so they don't fight over the db.
+pub async fn run(state: App) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        api::expire_transactions(&state).await;
+        sessions::bill_exhausted_sessions(&state).await;
+        holds::expire_holds(&state).await;
+        subscriptions::charge_subscriptions(&state).await;
+    }
+}
@@ -0,0 +1,201 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::AuthUser,
+    ledger::{capture_hold, place_hold, TransferError},
+    money::{Money, MoneyError},
+    registry::log_event,
+    util::get_random_string,
+    App,
+};
+
+/// How long a paid claim may last if the user doesn't say, the hold covers this many minutes
+pub const DEFAULT_MAX_MINUTES: i64 = 60;
+/// A day, so a forgotten claim can't lock away all of someones money forever
+pub const MAX_MINUTES: i64 = 24 * 60;
+
+/// Someone using a paid schmervice, billed per started minute
+#[derive(Serialize, Debug)]
+pub struct Session {
+    pub id: String,
+    pub owner: String,
+    pub name: String,
+    pub username: String,
+    /// per minute
+    pub rate: Money,
+    pub currency: String,
+    pub max_minutes: i64,
+    pub started: i64,
+    pub ended: Option<i64>,
+    pub cost: Option<Money>,
+}
+
+struct OpenSession {
+    id: String,
+    hold_id: String,
+    rate: i64,
+    max_minutes: i64,
+    started: i64,
+}
+
+/// Puts a hold for `max_minutes` of use on the users money and starts the clock
+pub(crate) async fn open_session(
+    conn: &mut SqliteConnection,
+    owner: &str,
+    name: &str,
+    username: &str,
+    rate: i64,
+    currency: &str,
+    max_minutes: i64,
+) -> Result<String, TransferError> {
+    let limit = rate
+        .checked_mul(max_minutes)
+        .ok_or(TransferError::InvalidAmount(MoneyError::Overflow))?;
+    let hold_id = place_hold(conn, username, owner, name, currency, limit).await?;
+    let id = get_random_string(8);
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO sessions (id, owner, name, username, hold_id, rate, currency, max_minutes, started)
+         VALUES (?,?,?,?,?,?,?,?,?);",
+        id,
+        owner,
+        name,
+        username,
+        hold_id,
+        rate,
+        currency,
+        max_minutes,
+        now
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(id)
+}
+
+/// Bills the open session of the schmervice, if there is one, and gives the rest
+/// of the hold back. Every started minute counts. Returns what it cost.
+pub(crate) async fn close_session(
+    conn: &mut SqliteConnection,
+    owner: &str,
+    name: &str,
+) -> Result<Option<i64>, TransferError> {
+    let session = sqlx::query_as!(
+        OpenSession,
+        "SELECT id, hold_id, rate, max_minutes, started FROM sessions
+         WHERE owner = ? AND name = ? AND ended IS NULL;",
+        owner,
+        name
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(session) = session else {
+        return Ok(None);
+    };
+    let now = chrono::Utc::now().timestamp();
+    let minutes = ((now - session.started + 59) / 60).clamp(1, session.max_minutes);
+    let cost = session.rate * minutes;
+    capture_hold(conn, &session.hold_id, cost).await?;
+    sqlx::query!(
+        "UPDATE sessions SET ended = ?, cost = ? WHERE id = ?;",
+        now,
+        cost,
+        session.id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(Some(cost))
+}
+
+/// Ends the sessions that used up their hold and frees their schmervices,
+/// returns the owners and users of the sessions
+async fn close_exhausted_sessions(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<String>, TransferError> {
+    let now = chrono::Utc::now().timestamp();
+    let exhausted = sqlx::query!(
+        "SELECT owner, name, username FROM sessions
+         WHERE ended IS NULL AND started + max_minutes * 60 <= ?;",
+        now
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut users = Vec::new();
+    for s in exhausted {
+        close_session(conn, &s.owner, &s.name).await?;
+        sqlx::query!(
+            "UPDATE schmervices SET in_use_by = NULL, claimed_at = NULL WHERE owner = ? AND name = ?;",
+            s.owner,
+            s.name
+        )
+        .execute(&mut *conn)
+        .await?;
+        log_event(conn, &s.owner, &s.name, &s.username, "expire").await?;
+        users.extend([s.owner, s.username]);
+    }
+    Ok(users)
+}
+
+/// Bills and ends the sessions that used up their hold
+pub(crate) async fn bill_exhausted_sessions(state: &App) {
+    let result = match state.db.begin().await {
+        Ok(mut tx) => match close_exhausted_sessions(&mut tx).await {
+            Ok(users) => tx
+                .commit()
+                .await
+                .map(|_| users)
+                .map_err(TransferError::from),
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err.into()),
+    };
+    match result {
+        Ok(users) if !users.is_empty() => state.events.changed(users),
+        Ok(_) => {}
+        Err(err) => println!("failed to close sessions: {err}"),
+    }
+}
+
+/// The sessions of the logged in user, as user or as owner
+pub(crate) async fn list_sessions(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<Session>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let rows = sqlx::query!(
+        "SELECT id, owner, sessions.name, username, rate, sessions.currency, max_minutes, started, ended,
+            cost, currencies.decimals
+         FROM sessions JOIN currencies ON currencies.code = sessions.currency
+         WHERE owner = ?1 OR username = ?1 ORDER BY started DESC LIMIT 50;",
+        user
+    )
+    .fetch_all(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(
+        rows.into_iter()
+            .map(|r| Session {
+                id: r.id,
+                owner: r.owner,
+                name: r.name,
+                username: r.username,
+                rate: Money::new(r.rate, r.decimals as u8),
+                currency: r.currency,
+                max_minutes: r.max_minutes,
+                started: r.started,
+                ended: r.ended,
+                cost: r.cost.map(|c| Money::new(c, r.decimals as u8)),
+            })
+            .collect(),
+    ))
+}
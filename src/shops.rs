@@ -0,0 +1,384 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Html,
+    routing::{get, post},
+    Json, Router,
+};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::AuthUser,
+    catalog::{get_products, Product},
+    db_utils::is_frozen,
+    layout::{render_page, Layout},
+    util::{get_random_string, ApiRequest},
+    App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/", get(search_shops))
+        .route("/:id", get(get_shop))
+        .route("/register_shop", post(register_shop))
+        .route("/update_shop/:id", post(update_shop))
+        .route("/set_open/:id", post(set_open))
+        .route("/remove_shop/:id", post(remove_shop))
+}
+
+pub fn default_dimension() -> String {
+    "minecraft:overworld".to_owned()
+}
+
+#[derive(Serialize, Debug)]
+pub struct Shop {
+    pub id: String,
+    pub owner: String,
+    pub owner_display_name: String,
+    pub name: String,
+    pub description: String,
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub dimension: String,
+    pub open: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct ShopDetails {
+    #[serde(flatten)]
+    shop: Shop,
+    products: Vec<Product>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ShopData {
+    name: String,
+    #[serde(default)]
+    description: String,
+    x: i64,
+    y: i64,
+    z: i64,
+    #[serde(default = "default_dimension")]
+    dimension: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenData {
+    open: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ShopFilter {
+    /// searched for in the name and description
+    q: Option<String>,
+    owner: Option<String>,
+    dimension: Option<String>,
+    open: Option<bool>,
+}
+
+impl ShopData {
+    fn is_valid(&self) -> bool {
+        let name = self.name.trim();
+        !name.is_empty()
+            && name.len() <= 32
+            && self.description.len() <= 256
+            && !self.dimension.is_empty()
+            && self.dimension.len() <= 64
+    }
+}
+
+pub async fn find_shops(
+    conn: &mut SqliteConnection,
+    filter: &ShopFilter,
+) -> sqlx::Result<Vec<Shop>> {
+    // empty search fields from the html form mean "don't filter"
+    let q = filter
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(|q| format!("%{q}%"));
+    let owner = filter.owner.as_deref().filter(|o| !o.is_empty());
+    let dimension = filter.dimension.as_deref().filter(|d| !d.is_empty());
+    sqlx::query_as!(
+        Shop,
+        r#"SELECT id, owner, users.display_name as owner_display_name, name, description,
+            x, y, z, dimension, open
+           FROM shops JOIN users ON users.username = shops.owner
+           WHERE (?1 IS NULL OR name LIKE ?1 OR description LIKE ?1)
+            AND (?2 IS NULL OR owner = ?2)
+            AND (?3 IS NULL OR dimension = ?3)
+            AND (?4 IS NULL OR open = ?4)
+           ORDER BY open DESC, name;"#,
+        q,
+        owner,
+        dimension,
+        filter.open
+    )
+    .fetch_all(conn)
+    .await
+}
+
+async fn fetch_shop(conn: &mut SqliteConnection, id: &str) -> sqlx::Result<Option<Shop>> {
+    sqlx::query_as!(
+        Shop,
+        r#"SELECT id, owner, users.display_name as owner_display_name, name, description,
+            x, y, z, dimension, open
+           FROM shops JOIN users ON users.username = shops.owner
+           WHERE id = ?;"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+async fn search_shops(
+    State(state): State<App>,
+    Query(filter): Query<ShopFilter>,
+) -> Result<Json<Vec<Shop>>, StatusCode> {
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let shops = find_shops(&mut conn, &filter)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(shops))
+}
+
+/// The shop with everything in its owners catalog
+async fn get_shop(
+    State(state): State<App>,
+    Path(id): Path<String>,
+) -> Result<Json<ShopDetails>, StatusCode> {
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let shop = fetch_shop(&mut conn, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let products = get_products(&mut conn, &shop.owner)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(ShopDetails { shop, products }))
+}
+
+async fn register_shop(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<ShopData>,
+) -> Result<Json<String>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    if !data.is_valid() {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    match is_frozen(&state, &user).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    let id = get_random_string(8);
+    let now = chrono::Utc::now().timestamp();
+    let name = data.name.trim();
+    sqlx::query!(
+        "INSERT INTO shops (id, owner, name, description, x, y, z, dimension, created)
+         VALUES (?,?,?,?,?,?,?,?,?);",
+        id,
+        user,
+        name,
+        data.description,
+        data.x,
+        data.y,
+        data.z,
+        data.dimension,
+        now
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(id))
+}
+
+async fn update_shop(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+    ApiRequest(data): ApiRequest<ShopData>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    if !data.is_valid() {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    let name = data.name.trim();
+    let r = sqlx::query!(
+        "UPDATE shops SET name = ?, description = ?, x = ?, y = ?, z = ?, dimension = ?
+         WHERE id = ? AND owner = ?;",
+        name,
+        data.description,
+        data.x,
+        data.y,
+        data.z,
+        data.dimension,
+        id,
+        user
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn set_open(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+    ApiRequest(data): ApiRequest<OpenData>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let r = sqlx::query!(
+        "UPDATE shops SET open = ? WHERE id = ? AND owner = ?;",
+        data.open,
+        id,
+        user
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn remove_shop(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let r = sqlx::query!("DELETE FROM shops WHERE id = ? AND owner = ?;", id, user)
+        .execute(
+            &mut *state
+                .db
+                .acquire()
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    Ok(StatusCode::OK)
+}
+
+/// Browsable list of every shop, takes the same filters as /api/shops
+pub async fn shops_page(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Query(filter): Query<ShopFilter>,
+) -> Result<Html<String>, StatusCode> {
+    let user = user.map(|(name, _)| name);
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let shops = find_shops(&mut conn, &filter)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut listings = Vec::with_capacity(shops.len());
+    for shop in shops {
+        let products = get_products(&mut conn, &shop.owner)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        listings.push((shop, products));
+    }
+    let search = filter.q.unwrap_or_default();
+
+    let html = render_page(move || {
+        let shops = listings
+            .into_iter()
+            .map(|(shop, products)| {
+                let products = products
+                    .into_iter()
+                    .map(|p| {
+                        view! {
+                            <li>
+                                {p.title}" - "{p.price.to_string()}" "{p.currency}
+                                {(p.stock <= 0).then_some(" (sold out)")}
+                            </li>
+                        }
+                    })
+                    .collect_view();
+                view! {
+                    <section>
+                        <h2>
+                            {shop.name}
+                            <span class:text-red-600=!shop.open>
+                                {if shop.open { " (open)" } else { " (closed)" }}
+                            </span>
+                        </h2>
+                        <p>"by "{shop.owner_display_name}</p>
+                        <p>{shop.x}" "{shop.y}" "{shop.z}" in "{shop.dimension}</p>
+                        <p>{shop.description}</p>
+                        <ul>{products}</ul>
+                    </section>
+                }
+            })
+            .collect_view();
+
+        view! {
+            <Layout title="Shops" user=user>
+                <h1>Shops</h1>
+                <form method="get" action="/shops">
+                    <input type="text" name="q" placeholder="Search" value=search/>
+                    <button class="button">Search</button>
+                </form>
+                {shops}
+            </Layout>
+        }
+    });
+
+    Ok(html)
+}
@@ -0,0 +1,284 @@
+//! The states of everything that moves money and which ones can follow each other.
+//! They're stored as the INTEGER of their variant, so the numbers are pinned
+//! and an existing one must never change.
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+/// Where a transaction is in its life.
+///
+/// ```text
+/// Pending -> Accepted -> Refunded
+///         -> Rejected
+///         -> Cancelled
+///         -> Expired
+/// ```
+#[derive(sqlx::Type, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionStatus {
+    /// waiting for the buyer
+    Pending = 0,
+    /// buyer paid
+    Accepted = 1,
+    /// buyer said no
+    Rejected = 2,
+    /// seller took it back before the buyer answered
+    Cancelled = 3,
+    /// nobody answered in time
+    Expired = 4,
+    /// seller gave the money back after it was accepted
+    Refunded = 5,
+}
+
+impl TransactionStatus {
+    pub fn can_become(self, next: TransactionStatus) -> bool {
+        use TransactionStatus::*;
+        matches!(
+            (self, next),
+            (Pending, Accepted | Rejected | Cancelled | Expired) | (Accepted, Refunded)
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TransactionStatus::Pending => "pending",
+            TransactionStatus::Accepted => "accepted",
+            TransactionStatus::Rejected => "rejected",
+            TransactionStatus::Cancelled => "cancelled",
+            TransactionStatus::Expired => "expired",
+            TransactionStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl Display for TransactionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where a payment hold is in its life.
+///
+/// ```text
+/// Requested -> Held -> Captured
+///                   -> Released
+///           -> Declined
+/// ```
+#[derive(sqlx::Type, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+#[serde(rename_all = "lowercase")]
+pub enum HoldStatus {
+    /// the money is taken from the buyer but the seller doesn't have it yet
+    Held = 0,
+    /// the seller got (part of) the money, the rest went back to the buyer
+    Captured = 1,
+    /// all of the money went back to the buyer
+    Released = 2,
+    /// waiting for the buyer to approve, no money moved yet
+    Requested = 3,
+    /// the buyer said no
+    Declined = 4,
+}
+
+impl HoldStatus {
+    pub fn can_become(self, next: HoldStatus) -> bool {
+        use HoldStatus::*;
+        matches!(
+            (self, next),
+            (Requested, Held | Declined) | (Held, Captured | Released)
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HoldStatus::Held => "held",
+            HoldStatus::Captured => "captured",
+            HoldStatus::Released => "released",
+            HoldStatus::Requested => "requested",
+            HoldStatus::Declined => "declined",
+        }
+    }
+}
+
+impl Display for HoldStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where a subscription is in its life.
+///
+/// ```text
+/// Requested -> Active <-> PastDue -> Lapsed
+///           -> Declined
+/// Requested, Active, PastDue -> Cancelled
+/// ```
+#[derive(sqlx::Type, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    /// waiting for the buyer
+    Requested = 0,
+    /// paid up, gets charged every interval
+    Active = 1,
+    /// the last charge failed, retrying until the grace period is over
+    PastDue = 2,
+    /// the grace period ran out without a successful charge
+    Lapsed = 3,
+    /// buyer or seller ended it
+    Cancelled = 4,
+    /// the buyer said no
+    Declined = 5,
+}
+
+impl SubscriptionStatus {
+    pub fn can_become(self, next: SubscriptionStatus) -> bool {
+        use SubscriptionStatus::*;
+        matches!(
+            (self, next),
+            (Requested, Active | Declined | Cancelled)
+                | (Active, PastDue | Cancelled)
+                | (PastDue, Active | Lapsed | Cancelled)
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SubscriptionStatus::Requested => "requested",
+            SubscriptionStatus::Active => "active",
+            SubscriptionStatus::PastDue => "past_due",
+            SubscriptionStatus::Lapsed => "lapsed",
+            SubscriptionStatus::Cancelled => "cancelled",
+            SubscriptionStatus::Declined => "declined",
+        }
+    }
+}
+
+impl Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where a payment link is in its life.
+///
+/// ```text
+/// Open -> Paid
+///      -> Cancelled
+/// ```
+#[derive(sqlx::Type, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i64)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentLinkStatus {
+    /// anyone can pay it
+    Open = 0,
+    /// somebody paid it, links are single use
+    Paid = 1,
+    /// the seller took it back
+    Cancelled = 2,
+}
+
+impl PaymentLinkStatus {
+    pub fn can_become(self, next: PaymentLinkStatus) -> bool {
+        use PaymentLinkStatus::*;
+        matches!((self, next), (Open, Paid | Cancelled))
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PaymentLinkStatus::Open => "open",
+            PaymentLinkStatus::Paid => "paid",
+            PaymentLinkStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl Display for PaymentLinkStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every pair that isn't listed as allowed has to be refused
+    fn check<T: Copy + PartialEq + std::fmt::Debug>(
+        all: &[T],
+        allowed: &[(T, T)],
+        can_become: impl Fn(T, T) -> bool,
+    ) {
+        for &from in all {
+            for &to in all {
+                assert_eq!(
+                    can_become(from, to),
+                    allowed.contains(&(from, to)),
+                    "{from:?} -> {to:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transaction_transitions() {
+        use TransactionStatus::*;
+        check(
+            &[Pending, Accepted, Rejected, Cancelled, Expired, Refunded],
+            &[
+                (Pending, Accepted),
+                (Pending, Rejected),
+                (Pending, Cancelled),
+                (Pending, Expired),
+                (Accepted, Refunded),
+            ],
+            TransactionStatus::can_become,
+        );
+    }
+
+    #[test]
+    fn hold_transitions() {
+        use HoldStatus::*;
+        check(
+            &[Requested, Held, Captured, Released, Declined],
+            &[
+                (Requested, Held),
+                (Requested, Declined),
+                (Held, Captured),
+                (Held, Released),
+            ],
+            HoldStatus::can_become,
+        );
+    }
+
+    #[test]
+    fn subscription_transitions() {
+        use SubscriptionStatus::*;
+        check(
+            &[Requested, Active, PastDue, Lapsed, Cancelled, Declined],
+            &[
+                (Requested, Active),
+                (Requested, Declined),
+                (Requested, Cancelled),
+                (Active, PastDue),
+                (Active, Cancelled),
+                (PastDue, Active),
+                (PastDue, Lapsed),
+                (PastDue, Cancelled),
+            ],
+            SubscriptionStatus::can_become,
+        );
+    }
+
+    #[test]
+    fn payment_link_transitions() {
+        use PaymentLinkStatus::*;
+        check(
+            &[Open, Paid, Cancelled],
+            &[(Open, Paid), (Open, Cancelled)],
+            PaymentLinkStatus::can_become,
+        );
+    }
+}
@@ -0,0 +1,522 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::post,
+    Json, Router,
+};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::AuthUser,
+    config,
+    db_utils::is_frozen,
+    ledger::{default_currency, record_transfer, to_minor_units, TransferError},
+    money::Money,
+    notifications::notify_user,
+    status::SubscriptionStatus,
+    util::{get_random_string, ApiRequest},
+    App,
+};
+
+/// How long to wait before trying a failed charge again
+pub fn get_retry_interval() -> Duration {
+    Duration::minutes(config::get().ttl.subscription_retry_minutes)
+}
+
+/// How long a charge may stay unpaid before the subscription lapses
+pub fn get_grace_period() -> Duration {
+    Duration::hours(config::get().ttl.subscription_grace_hours)
+}
+
+/// A year, anything longer isn't really recurring
+pub const MAX_INTERVAL_HOURS: i64 = 24 * 365;
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/request_subscription", post(request_subscription))
+        .route("/approve_subscription/:id", post(approve_subscription))
+        .route("/decline_subscription/:id", post(decline_subscription))
+        .route("/cancel_subscription/:id", post(cancel_subscription))
+        .route("/list_subscriptions", post(list_subscriptions))
+}
+
+#[derive(Deserialize, Debug)]
+struct RequestSubscription {
+    buyer: String,
+    name: String,
+    amount: Money,
+    #[serde(default = "default_currency")]
+    currency: String,
+    /// how often to charge, 168 for weekly
+    interval_hours: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Subscription {
+    pub id: String,
+    pub buyer: String,
+    pub seller: String,
+    pub name: String,
+    pub amount: Money,
+    pub currency: String,
+    pub interval_hours: i64,
+    pub status: SubscriptionStatus,
+    pub next_charge: Option<i64>,
+    pub failed_attempts: i64,
+}
+
+struct StoredSubscription {
+    id: String,
+    buyer: String,
+    seller: String,
+    name: String,
+    amount: i64,
+    currency: String,
+    decimals: i64,
+    interval_hours: i64,
+    status: SubscriptionStatus,
+    next_charge: Option<i64>,
+    due_since: Option<i64>,
+    failed_attempts: i64,
+}
+
+impl StoredSubscription {
+    fn price(&self) -> String {
+        format!(
+            "{} {}",
+            Money::new(self.amount, self.decimals as u8),
+            self.currency
+        )
+    }
+}
+
+impl From<StoredSubscription> for Subscription {
+    fn from(s: StoredSubscription) -> Self {
+        Subscription {
+            id: s.id,
+            buyer: s.buyer,
+            seller: s.seller,
+            name: s.name,
+            amount: Money::new(s.amount, s.decimals as u8),
+            currency: s.currency,
+            interval_hours: s.interval_hours,
+            status: s.status,
+            next_charge: s.next_charge,
+            failed_attempts: s.failed_attempts,
+        }
+    }
+}
+
+async fn fetch_subscription(
+    conn: &mut SqliteConnection,
+    id: &str,
+) -> Result<StoredSubscription, StatusCode> {
+    sqlx::query_as!(
+        StoredSubscription,
+        r#"SELECT id, buyer, seller, subscriptions.name, amount, currency, decimals,
+            interval_hours, status as "status: SubscriptionStatus", next_charge, due_since,
+            failed_attempts
+           FROM subscriptions JOIN currencies ON currencies.code = subscriptions.currency
+           WHERE id = ?;"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn set_status(
+    conn: &mut SqliteConnection,
+    id: &str,
+    status: SubscriptionStatus,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE subscriptions SET status = ? WHERE id = ?;",
+        status,
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Moves one period worth of money, frozen accounts count as not being able to pay
+async fn charge(
+    conn: &mut SqliteConnection,
+    sub: &StoredSubscription,
+) -> Result<(), TransferError> {
+    let frozen = sqlx::query!(
+        "SELECT username FROM users WHERE username IN (?, ?) AND frozen != 0;",
+        sub.buyer,
+        sub.seller
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if frozen.is_some() {
+        return Err(TransferError::InsufficientFunds);
+    }
+    record_transfer(
+        conn,
+        &sub.buyer,
+        &sub.seller,
+        &sub.name,
+        &sub.currency,
+        sub.amount,
+    )
+    .await?;
+    Ok(())
+}
+
+/// When to charge next after paying the period that was due at `due`.
+/// A late payment starts a fresh period instead of paying for the time it was late,
+/// and periods missed while the server was down are skipped instead of all charged at once.
+fn next_charge(due: i64, late: bool, interval: i64, now: i64) -> i64 {
+    let from = if late { now } else { due };
+    from + ((now - from) / interval + 1) * interval
+}
+
+/// Tries to charge one due subscription and moves it along its life
+async fn process_due(
+    conn: &mut SqliteConnection,
+    sub: StoredSubscription,
+) -> Result<(), TransferError> {
+    let now = chrono::Utc::now().timestamp();
+    let due = sub.next_charge.unwrap_or(now);
+    match charge(conn, &sub).await {
+        Ok(()) => {
+            let next = next_charge(due, sub.due_since.is_some(), sub.interval_hours * 3600, now);
+            sqlx::query!(
+                "UPDATE subscriptions SET status = ?, next_charge = ?, due_since = NULL,
+                    failed_attempts = 0
+                 WHERE id = ?;",
+                SubscriptionStatus::Active,
+                next,
+                sub.id
+            )
+            .execute(&mut *conn)
+            .await?;
+            let msg = format!("Paid {} for {} to {}", sub.price(), sub.name, sub.seller);
+            notify_user(conn, &sub.buyer, &msg).await?;
+            let msg = format!("Got {} for {} from {}", sub.price(), sub.name, sub.buyer);
+            notify_user(conn, &sub.seller, &msg).await?;
+        }
+        Err(TransferError::InsufficientFunds) => {
+            let due_since = sub.due_since.unwrap_or(due);
+            if now - due_since > get_grace_period().num_seconds() {
+                set_status(conn, &sub.id, SubscriptionStatus::Lapsed).await?;
+                let msg = format!("{} lapsed because it wasn't paid", sub.name);
+                notify_user(conn, &sub.buyer, &msg).await?;
+                notify_user(conn, &sub.seller, &msg).await?;
+                return Ok(());
+            }
+            let retry = now + get_retry_interval().num_seconds();
+            sqlx::query!(
+                "UPDATE subscriptions SET status = ?, next_charge = ?, due_since = ?,
+                    failed_attempts = failed_attempts + 1
+                 WHERE id = ?;",
+                SubscriptionStatus::PastDue,
+                retry,
+                due_since,
+                sub.id
+            )
+            .execute(&mut *conn)
+            .await?;
+            // only tell them once, not on every retry
+            if sub.failed_attempts == 0 {
+                let msg = format!("Couldn't pay {} for {}, retrying", sub.price(), sub.name);
+                notify_user(conn, &sub.buyer, &msg).await?;
+                notify_user(conn, &sub.seller, &msg).await?;
+            }
+        }
+        // the other account or the currency is gone, nothing to charge anymore
+        Err(TransferError::UnknownAccount | TransferError::UnknownCurrency) => {
+            set_status(conn, &sub.id, SubscriptionStatus::Cancelled).await?;
+        }
+        Err(err) => return Err(err),
+    }
+    Ok(())
+}
+
+async fn run_due_charges(state: &App) -> Result<(), TransferError> {
+    let now = chrono::Utc::now().timestamp();
+    let due = sqlx::query_as!(
+        StoredSubscription,
+        r#"SELECT id, buyer, seller, subscriptions.name, amount, currency, decimals,
+            interval_hours, status as "status: SubscriptionStatus", next_charge, due_since,
+            failed_attempts
+           FROM subscriptions JOIN currencies ON currencies.code = subscriptions.currency
+           WHERE status IN (?, ?) AND next_charge <= ?;"#,
+        SubscriptionStatus::Active,
+        SubscriptionStatus::PastDue,
+        now
+    )
+    .fetch_all(&*state.db)
+    .await?;
+    // one db transaction each, so one broken subscription doesn't hold up the others
+    for sub in due {
+        let (id, buyer, seller) = (sub.id.clone(), sub.buyer.clone(), sub.seller.clone());
+        let mut tx = state.db.begin().await?;
+        // dropping tx rolls back only this one, it'll be tried again next tick
+        if let Err(err) = process_due(&mut tx, sub).await {
+            println!("failed to charge subscription {id}: {err}");
+            continue;
+        }
+        tx.commit().await?;
+        state.events.changed([&buyer, &seller]);
+    }
+    Ok(())
+}
+
+/// Charges due subscriptions, retries failed ones and lets them lapse after the grace period
+pub(crate) async fn charge_subscriptions(state: &App) {
+    if let Err(err) = run_due_charges(state).await {
+        println!("failed to charge subscriptions: {err}");
+    }
+}
+
+/// Seller asks the buyer to be charged every `interval_hours`, returns the id
+async fn request_subscription(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    ApiRequest(data): ApiRequest<RequestSubscription>,
+) -> Result<Json<String>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    if !data.amount.is_positive()
+        || user == data.buyer
+        || !(1..=MAX_INTERVAL_HOURS).contains(&data.interval_hours)
+    {
+        Err(StatusCode::BAD_REQUEST)?;
+    }
+    match is_frozen(&state, &user).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)?,
+    }
+    match is_frozen(&state, &data.buyer).await {
+        Ok(false) => {}
+        Ok(true) => Err(StatusCode::FORBIDDEN)?,
+        Err(_) => Err(StatusCode::NOT_FOUND)?,
+    }
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let amount = to_minor_units(&mut conn, &data.currency, data.amount).await?;
+    let id = get_random_string(8);
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, buyer, seller, name, amount, currency, interval_hours,
+            status, created)
+         VALUES (?,?,?,?,?,?,?,?,?);",
+        id,
+        data.buyer,
+        user,
+        data.name,
+        amount,
+        data.currency,
+        data.interval_hours,
+        SubscriptionStatus::Requested,
+        now
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let msg = format!("{user} wants to charge you for {} regularly", data.name);
+    notify_user(&mut conn, &data.buyer, &msg)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(id))
+}
+
+/// Buyer agrees, the first period gets paid right away
+async fn approve_subscription(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let sub = fetch_subscription(&mut tx, &id).await?;
+    if sub.buyer != user {
+        Err(StatusCode::NOT_FOUND)?;
+    }
+    if sub.status != SubscriptionStatus::Requested {
+        Err(StatusCode::CONFLICT)?;
+    }
+    charge(&mut tx, &sub).await?;
+    let next = chrono::Utc::now().timestamp() + sub.interval_hours * 3600;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = ?, next_charge = ? WHERE id = ?;",
+        SubscriptionStatus::Active,
+        next,
+        id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let msg = format!("{user} subscribed to {} for {}", sub.name, sub.price());
+    notify_user(&mut tx, &sub.seller, &msg)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.events.changed([&user, &sub.seller]);
+    Ok(StatusCode::OK)
+}
+
+async fn decline_subscription(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let r = sqlx::query!(
+        "UPDATE subscriptions SET status = ? WHERE id = ? AND buyer = ? AND status = ?;",
+        SubscriptionStatus::Declined,
+        id,
+        user,
+        SubscriptionStatus::Requested
+    )
+    .execute(
+        &mut *state
+            .db
+            .acquire()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if r.rows_affected() == 0 {
+        Err(StatusCode::CONFLICT)?;
+    }
+    Ok(StatusCode::OK)
+}
+
+/// Buyer or seller ends the subscription, nothing already paid gets refunded
+async fn cancel_subscription(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let sub = fetch_subscription(&mut tx, &id).await?;
+    let other = if sub.buyer == user {
+        &sub.seller
+    } else if sub.seller == user {
+        &sub.buyer
+    } else {
+        Err(StatusCode::NOT_FOUND)?
+    };
+    if !sub.status.can_become(SubscriptionStatus::Cancelled) {
+        Err(StatusCode::CONFLICT)?;
+    }
+    set_status(&mut tx, &id, SubscriptionStatus::Cancelled)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let msg = format!("{user} cancelled {}", sub.name);
+    notify_user(&mut tx, other, &msg)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn get_subscriptions(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> sqlx::Result<Vec<Subscription>> {
+    let rows = sqlx::query_as!(
+        StoredSubscription,
+        r#"SELECT id, buyer, seller, subscriptions.name, amount, currency, decimals,
+            interval_hours, status as "status: SubscriptionStatus", next_charge, due_since,
+            failed_attempts
+           FROM subscriptions JOIN currencies ON currencies.code = subscriptions.currency
+           WHERE buyer = ?1 OR seller = ?1 ORDER BY created DESC;"#,
+        username
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows.into_iter().map(Subscription::from).collect())
+}
+
+/// The subscriptions of the logged in user, as buyer or seller
+async fn list_subscriptions(
+    State(state): State<App>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<Subscription>>, StatusCode> {
+    let user = match user {
+        Some((name, _)) => name,
+        None => Err(StatusCode::UNAUTHORIZED)?,
+    };
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let subscriptions = get_subscriptions(&mut conn, &user)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(subscriptions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 3600;
+
+    #[test]
+    fn next_charge_keeps_the_schedule() {
+        let due = 1_700_000_000;
+        assert_eq!(next_charge(due, false, DAY, due), due + DAY);
+        // charged by a later tick, still due at the same time of day
+        assert_eq!(next_charge(due, false, DAY, due + 120), due + DAY);
+    }
+
+    #[test]
+    fn next_charge_skips_missed_periods() {
+        let due = 1_700_000_000;
+        // the server was down for 3.5 days, only the current period is paid
+        let now = due + 3 * DAY + DAY / 2;
+        let next = next_charge(due, false, DAY, now);
+        assert_eq!(next, due + 4 * DAY);
+        assert!(next > now);
+        // exactly on a later due time, that one counts as paid
+        assert_eq!(next_charge(due, false, DAY, due + 2 * DAY), due + 3 * DAY);
+    }
+
+    #[test]
+    fn next_charge_after_late_payment() {
+        let due = 1_700_000_000;
+        let now = due + 5 * 3600;
+        assert_eq!(next_charge(due, true, DAY, now), now + DAY);
+    }
+}
@@ -36,6 +36,12 @@ pub async fn get_displayname_from_valid_auth_token(app: &App, token: &str) -> Op
     // w.map_err(|err| {println!("join err: {}",err);err}).ok()
 }
 
+pub fn format_timestamp(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
 macro_rules! err_handle {
     ($req_type:expr,$json:expr,$html:expr) => {
         match $req_type {
@@ -69,6 +75,8 @@ pub fn get_requested_type(headers: &HeaderMap<HeaderValue>) -> Option<RequestTyp
         Some(header) if header.contains("application/json") => Some(RequestTypeEnum::Json),
         //  Fuking stupid hack !!!Risky!!!
         Some(header) if header.contains("custom/ws") => Some(RequestTypeEnum::Json),
+        // EventSource can't set headers, so it logs in with the cookie like a page
+        Some(header) if header.contains("text/event-stream") => Some(RequestTypeEnum::Html),
 
         Some(header) if header.contains("text/html") => Some(RequestTypeEnum::Html),
         Some("*/*") => match htmx {
@@ -109,3 +117,17 @@ where
         })
     }
 }
+
+/// What went wrong, for people looking at a page instead of a status code
+pub fn describe_status(code: StatusCode) -> &'static str {
+    match code {
+        StatusCode::UNAUTHORIZED => "You need to log in",
+        StatusCode::PAYMENT_REQUIRED => "Not enough money",
+        StatusCode::FORBIDDEN => "The account is frozen",
+        StatusCode::NOT_FOUND => "Not found",
+        StatusCode::CONFLICT => "Already answered or sold out",
+        StatusCode::GONE => "Expired",
+        StatusCode::BAD_REQUEST => "That doesn't look right",
+        _ => "Something went wrong",
+    }
+}